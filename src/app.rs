@@ -3,60 +3,132 @@
 
 /// The application is started and terminated.
 ///
-/// 1. Subscribe to Redmine Atom.
-/// 2. Get action that have been updated.
-/// 3. Notify Slack.
+/// In daemon mode (`--interval`), the cycle is repeated until a signal is received.
 pub fn run() -> proc_exit::ExitResult {
     let cli_args = crate::cli_args::parse();
-    if cli_args.verbose {
-        crate::log::set_level(crate::log::Severity::Debug);
+
+    if let Some(addr) = &cli_args.listen {
+        let readiness = crate::health::Readiness {
+            max_age: cli_args.interval.map(|i| i * cli_args.ready_intervals),
+            prev_data_path: cli_args.redmine.prev_redmine_data.clone(),
+        };
+        if let Err(err) = crate::server::start(addr, readiness) {
+            crate::log::error(&err.to_string()).category("app");
+            return Err(proc_exit::Exit::new(proc_exit::Code::FAILURE).with_message(err));
+        }
+    }
+
+    let Some(interval) = cli_args.interval else {
+        return observed_cycle(&cli_args);
+    };
+    loop {
+        if let Err(err) = observed_cycle(&cli_args) {
+            crate::log::error(&err.to_string()).category("app");
+        }
+        std::thread::sleep(interval);
     }
+}
+
+/// Exit when a termination signal is received.
+///
+/// The signal is logged instead of printed, so that it does not mix into `--jsonl -` output.
+pub fn exit_on_signal() {
+    use signal_hook::consts::{SIGHUP, SIGINT, SIGQUIT, SIGTERM};
+    let mut signals = signal_hook::iterator::Signals::new([SIGHUP, SIGINT, SIGQUIT, SIGTERM])
+        .expect("Error setting signal handler");
+    std::thread::spawn(move || {
+        if let Some(sig) = signals.forever().next() {
+            crate::log::default(&format!("Received signal {:?}", sig)).category("app");
+            proc_exit::exit(Err(proc_exit::Exit::new(proc_exit::Code::FAILURE)));
+        }
+    });
+}
 
+/// Run the cycle with metrics and health status.
+fn observed_cycle(cli_args: &crate::cli_args::CliArgs) -> proc_exit::ExitResult {
+    let start = std::time::Instant::now();
+    let result = run_cycle(cli_args);
+    crate::metrics::observe_cycle(start.elapsed(), result.is_ok());
+    crate::health::record_cycle(result.as_ref().err().map(|e| e.to_string()).as_deref());
+    crate::ops::report(cli_args, result.as_ref().err());
+    result
+}
+
+/// One cycle of the application.
+///
+/// 1. Subscribe to Redmine Atom.
+/// 2. Get action that have been updated.
+/// 3. Notify each destination such as Slack.
+fn run_cycle(cli_args: &crate::cli_args::CliArgs) -> proc_exit::ExitResult {
     let mut ret = (proc_exit::Code::SUCCESS, None);
 
     // Subscribe to Redmine
     let redmine_args = &cli_args.redmine;
-    let prev_redmine_data = crate::redmine::load_prev_data(&redmine_args);
+    let prev_redmine_data = crate::redmine::load_prev_data(redmine_args);
     if let Err(err) = prev_redmine_data {
-        ret = (proc_exit::Code::FAILURE, Some(err.to_string()));
+        ret = (
+            proc_exit::Code::FAILURE,
+            Some(crate::log::redact(&err.to_string())),
+        );
         return Err(proc_exit::Exit::new(ret.0).with_message(ret.1.unwrap()));
     }
     let mut prev_redmine_data = prev_redmine_data.unwrap();
+    crate::health::set_prev_date(prev_redmine_data.prev_date());
 
+    // Private notes are marked only if some destination excludes them.
+    let notifiers = crate::notifier::from_args(cli_args);
+    let allow_private = &cli_args.delivery.allow_private;
+    let mark_private = notifiers
+        .iter()
+        .any(|n| !allow_private.iter().any(|d| d == n.name()));
     let updated_issues =
-        crate::redmine::refresh_updated_issues(&redmine_args, &mut prev_redmine_data);
+        crate::redmine::refresh_updated_issues(redmine_args, &mut prev_redmine_data, mark_private);
     if let Err(err) = updated_issues {
-        ret = (proc_exit::Code::FAILURE, Some(err.to_string()));
+        ret = (
+            proc_exit::Code::FAILURE,
+            Some(crate::log::redact(&err.to_string())),
+        );
         return Err(proc_exit::Exit::new(ret.0).with_message(ret.1.unwrap()));
     }
 
     // Check exists
     let mut updated_issues = updated_issues.unwrap();
+    crate::metrics::add_issues_detected(updated_issues.len());
+    crate::redact::apply(&cli_args.redact.redact, &mut updated_issues);
     if updated_issues.is_empty() {
-        println!("No updated issues.");
-        return Ok(());
+        if cli_args.jsonl.as_deref() != Some("-") {
+            println!("No updated issues.");
+        }
+        if !prev_redmine_data.has_pending() {
+            return Ok(());
+        }
     }
 
-    if let Some(notify_url) = &cli_args.slack.notify_url {
-        // Sort by updated_on
-        updated_issues.sort_by(|a, b| a.local_updated_time.cmp(&b.local_updated_time));
-        // Notify to Slack
-        let slack_args = &cli_args.slack;
-        for update in updated_issues {
-            let result = crate::slack::notify(slack_args, notify_url, &update);
-            if let Err(err) = result {
-                ret = (proc_exit::Code::FAILURE, Some(err.to_string()));
-                return Err(proc_exit::Exit::new(ret.0).with_message(ret.1.unwrap()));
-            }
+    // Sort by updated_on
+    updated_issues.sort_by_key(|a| a.local_updated_time);
+
+    // Notify to each destination
+    for notifier in notifiers {
+        let result = crate::notifier::dispatch(
+            &cli_args.delivery,
+            &mut prev_redmine_data,
+            notifier.as_ref(),
+            updated_issues.clone(),
+        );
+        // Continue to the other destinations, and the failure is returned after saving.
+        if let Err(err) = result {
+            ret = (proc_exit::Code::FAILURE, Some(append_error(ret.1, &err)));
         }
     }
 
-    // Save updated data
+    // Save updated data (including the undelivered updates queued by the destinations)
     let result = crate::redmine::save_purged_data(redmine_args, &mut prev_redmine_data);
     if let Err(err) = result {
-        ret = (proc_exit::Code::FAILURE, Some(err.to_string()));
+        ret = (proc_exit::Code::FAILURE, Some(append_error(ret.1, &err)));
         return Err(proc_exit::Exit::new(ret.0).with_message(ret.1.unwrap()));
     }
+    crate::metrics::set_tracked_issues(prev_redmine_data.tracked_issues());
+    crate::health::set_prev_date(prev_redmine_data.prev_date());
 
     // Exit Code
     let exit_code = ret.0;
@@ -72,3 +144,12 @@ pub fn run() -> proc_exit::ExitResult {
         }
     }
 }
+
+/// Append the error message to the previous one.
+fn append_error(msg: Option<String>, err: &anyhow::Error) -> String {
+    let err = crate::log::redact(&err.to_string());
+    match msg {
+        Some(msg) => format!("{}\n{}", msg, err),
+        None => err,
+    }
+}
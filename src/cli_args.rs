@@ -14,9 +14,82 @@ pub struct CliArgs {
     #[clap(flatten)]
     pub slack: SlackArgs,
 
-    /// Verbose mode
-    #[clap(long)]
-    pub verbose: bool,
+    /// Args for Microsoft Teams.
+    #[clap(flatten)]
+    pub teams: TeamsArgs,
+
+    /// Args for Discord.
+    #[clap(flatten)]
+    pub discord: DiscordArgs,
+
+    /// Args for Mattermost.
+    #[clap(flatten)]
+    pub mattermost: MattermostArgs,
+
+    /// Args for generic webhook.
+    #[clap(flatten)]
+    pub webhook: WebhookArgs,
+
+    /// Args for email.
+    #[clap(flatten)]
+    pub email: EmailArgs,
+
+    /// Args for local command.
+    #[clap(flatten)]
+    pub exec: ExecArgs,
+
+    /// Args for delivery to each destination.
+    #[clap(flatten)]
+    pub delivery: DeliveryArgs,
+
+    /// Args for retrying delivery.
+    #[clap(flatten)]
+    pub retry: RetryArgs,
+
+    /// Args for redaction.
+    #[clap(flatten)]
+    pub redact: RedactArgs,
+
+    /// Args for ops alert.
+    #[clap(flatten)]
+    pub ops: OpsArgs,
+
+    /// JSON Lines output path
+    ///
+    /// Each update is written as one JSON line with the rendered `message`.
+    /// If `-`, it is written to stdout and logs are written to stderr.
+    #[clap(long, value_name = "PATH")]
+    pub jsonl: Option<String>,
+
+    /// Template file path for the `message` of JSON Lines
+    ///
+    /// The default is plain text. See resources/jsonl-message.template.
+    #[clap(long, value_name = "PATH", requires = "jsonl")]
+    pub jsonl_template_path: Option<String>,
+
+    /// Daemon mode interval
+    ///
+    /// If specified, it keeps running and checks Redmine at this interval. (e.g. 5min)
+    /// Otherwise, it checks only once and exits.
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub interval: Option<std::time::Duration>,
+
+    /// HTTP server address for monitoring
+    ///
+    /// If specified, /metrics (Prometheus), /healthz and /readyz are served. It is useful with --interval.
+    /// e.g.) 0.0.0.0:9100
+    #[clap(long, value_name = "ADDR")]
+    pub listen: Option<String>,
+
+    /// Intervals within which the last cycle must succeed to be ready
+    ///
+    /// /readyz fails if no cycle has succeeded within --interval multiplied by this.
+    #[clap(long, value_name = "NUM", default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub ready_intervals: u32,
+
+    /// Args for logging.
+    #[clap(flatten)]
+    pub log: LogArgs,
 }
 
 #[derive(Clone, Debug, clap::Parser)]
@@ -82,6 +155,112 @@ pub struct FilterArgs {
     /// Specify by login id name or number. If me, the API Key itself is the target.
     #[clap(long, value_name = "USER(s)")]
     pub user: Option<Vec<String>>,
+
+    /// Target trackers name or number
+    ///
+    /// Filtered after getting issues. If not specified, all trackers will be targeted.
+    #[clap(long, value_name = "TRACKER(s)")]
+    pub tracker: Option<Vec<String>>,
+
+    /// Ignore trackers name or number.
+    ///
+    /// Filtered after getting issues. If not specified, no trackers will be ignored.
+    #[clap(long, value_name = "TRACKER(s)")]
+    pub ignore_tracker: Option<Vec<String>>,
+
+    /// Target statuses name or number
+    ///
+    /// Filtered after getting issues. If not specified, all statuses will be targeted.
+    #[clap(long, value_name = "STATUS(es)")]
+    pub status: Option<Vec<String>>,
+
+    /// Ignore statuses name or number.
+    ///
+    /// Filtered after getting issues. If not specified, no statuses will be ignored.
+    #[clap(long, value_name = "STATUS(es)")]
+    pub ignore_status: Option<Vec<String>>,
+
+    /// Target priorities name or number
+    ///
+    /// Filtered after getting issues. If not specified, all priorities will be targeted.
+    #[clap(long, value_name = "PRIORITY(s)")]
+    pub priority: Option<Vec<String>>,
+
+    /// Ignore priorities name or number.
+    ///
+    /// Filtered after getting issues. If not specified, no priorities will be ignored.
+    #[clap(long, value_name = "PRIORITY(s)")]
+    pub ignore_priority: Option<Vec<String>>,
+
+    /// Target categories name or number
+    ///
+    /// Filtered after getting issues. If not specified, all categories will be targeted.
+    #[clap(long, value_name = "CATEGORY(s)")]
+    pub category: Option<Vec<String>>,
+
+    /// Ignore categories name or number.
+    ///
+    /// Filtered after getting issues. If not specified, no categories will be ignored.
+    #[clap(long, value_name = "CATEGORY(s)")]
+    pub ignore_category: Option<Vec<String>>,
+
+    /// Target fixed versions name or number
+    ///
+    /// Filtered after getting issues. If not specified, all fixed versions will be targeted.
+    #[clap(long, value_name = "VERSION(s)")]
+    pub fixed_version: Option<Vec<String>>,
+
+    /// Ignore fixed versions name or number.
+    ///
+    /// Filtered after getting issues. If not specified, no fixed versions will be ignored.
+    #[clap(long, value_name = "VERSION(s)")]
+    pub ignore_fixed_version: Option<Vec<String>>,
+
+    /// Target assignees
+    ///
+    /// Filtered after getting issues. Specify by login id name or number. If me, the API Key itself is the target.
+    #[clap(long, value_name = "USER(s)")]
+    pub assignee: Option<Vec<String>>,
+
+    /// Ignore assignees
+    ///
+    /// Filtered after getting issues. Specify by login id name or number. If me, the API Key itself is the target.
+    #[clap(long, value_name = "USER(s)")]
+    pub ignore_assignee: Option<Vec<String>>,
+
+    /// Filter expression
+    ///
+    /// Filtered after getting issues.
+    /// e.g.) tracker = Bug AND (priority in [High, Urgent] OR assigned_to = me) AND NOT subject ~ /WIP/
+    #[clap(long, value_name = "EXPR")]
+    pub filter_expr: Option<crate::filter_expr::Expr>,
+
+    /// Notify only when any of these fields changed
+    ///
+    /// Specify the issue keys separated by comma. `notes` means comments.
+    /// New issues are always notified.
+    /// e.g.) status,assigned_to,priority,due_date,notes
+    #[clap(long, value_name = "FIELD(s)", value_delimiter = ',')]
+    pub notify_on_fields: Option<Vec<String>>,
+
+    /// Do not notify when only these fields changed
+    ///
+    /// Specify the issue keys separated by comma. `notes` means comments.
+    /// e.g.) done_ratio,spent_hours
+    #[clap(long, value_name = "FIELD(s)", value_delimiter = ',')]
+    pub ignore_fields: Option<Vec<String>>,
+
+    /// Ignore changes by the authors (e.g. bots)
+    ///
+    /// Specify by login id name or number. If me, the API Key itself is the target.
+    #[clap(long, value_name = "USER(s)")]
+    pub ignore_author: Option<Vec<String>>,
+
+    /// Ignore changes made by the owner of --redmine-api-key
+    ///
+    /// Same as `--ignore-author me`.
+    #[clap(long, requires = "redmine_api_key")]
+    pub ignore_own_changes: bool,
 }
 
 #[derive(Clone, Debug, clap::Parser)]
@@ -97,17 +276,390 @@ pub struct SlackArgs {
     /// If not specified, the default template will be used.
     #[clap(long, value_name = "PATH")]
     pub template_path: Option<String>,
+
+    /// Slack notification message template directory for each event kind
+    ///
+    /// Place created, updated, commented, closed or reopened with .template or .tera extension.
+    /// (e.g. closed.template)
+    /// If not found, the template of --template-path will be used.
+    #[clap(long, value_name = "DIR")]
+    pub template_dir: Option<String>,
+
+    /// Digest mode
+    ///
+    /// Batch updates into one summary message grouped by project per run.
+    #[clap(long)]
+    pub digest: bool,
+
+    /// Digest window
+    ///
+    /// Batch updates over this duration (e.g. 30min) instead of per run. It implies --digest.
+    /// Waiting updates are kept in the previous data file.
+    #[clap(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub digest_interval: Option<std::time::Duration>,
+
+    /// Slack digest message template file path
+    ///
+    /// If not specified, the default digest template will be used.
+    #[clap(long, value_name = "PATH")]
+    pub digest_template_path: Option<String>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct TeamsArgs {
+    /// Microsoft Teams incoming webhook URL to notify.
+    ///
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "URL")]
+    pub teams_url: Option<String>,
+
+    /// Teams Adaptive Card template file path
+    ///
+    /// The template should output JSON of the card. (e.g. teams-card.tera)
+    /// If not specified, the default card will be used.
+    #[clap(long, value_name = "PATH")]
+    pub teams_template_path: Option<String>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct DiscordArgs {
+    /// Discord webhook URL to notify.
+    ///
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "URL")]
+    pub discord_url: Option<String>,
+
+    /// Discord embed color of the priority
+    ///
+    /// Specify the priority name or number and the color.
+    /// Low, Normal, High, Urgent and Immediate have default colors.
+    ///
+    /// e.g.) Urgent=#ff0000
+    #[clap(long, value_name = "PRIORITY=#RRGGBB")]
+    pub discord_priority_color: Vec<crate::discord::PriorityColor>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct MattermostArgs {
+    /// Mattermost (or Rocket.Chat) incoming webhook URL to notify.
+    ///
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "URL")]
+    pub mattermost_url: Option<String>,
+
+    /// Compatible service of --mattermost-url
+    #[clap(long, value_name = "FLAVOR", value_enum, default_value = "mattermost")]
+    pub mattermost_flavor: crate::mattermost::Flavor,
+
+    /// Mattermost message template file path
+    ///
+    /// The message is Markdown. If not specified, the default template will be used.
+    #[clap(long, value_name = "PATH")]
+    pub mattermost_template_path: Option<String>,
+
+    /// Override the username of the webhook
+    #[clap(long, value_name = "NAME")]
+    pub mattermost_username: Option<String>,
+
+    /// Override the profile picture of the webhook
+    #[clap(long, value_name = "URL")]
+    pub mattermost_icon_url: Option<String>,
+
+    /// Override the channel of the webhook (e.g. town-square)
+    #[clap(long, value_name = "CHANNEL")]
+    pub mattermost_channel: Option<String>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct WebhookArgs {
+    /// Generic webhook URL to send JSON.
+    ///
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "URL")]
+    pub webhook_url: Option<String>,
+
+    /// HTTP method of the webhook
+    #[clap(long, value_name = "METHOD", default_value = "POST", value_parser = ["POST", "PUT", "PATCH"])]
+    pub webhook_method: String,
+
+    /// HTTP header of the webhook
+    ///
+    /// The whole value can be read from a file with @ or an environment variable with $,
+    /// and ${VAR} in the value is replaced with the environment variable.
+    ///
+    /// e.g.) "Authorization: Bearer ${WEBHOOK_TOKEN}"
+    #[clap(long, value_name = "NAME:VALUE")]
+    pub webhook_header: Vec<crate::webhook::Header>,
+
+    /// Webhook body template file path
+    ///
+    /// The template should output JSON. (.tera or TinyTemplate with the json formatter)
+    /// If not specified, the update itself is sent as JSON.
+    #[clap(long, value_name = "PATH")]
+    pub webhook_template_path: Option<String>,
+
+    /// Secret to sign the body with HMAC-SHA256
+    ///
+    /// If specified, the signature is sent in --webhook-hmac-header as `sha256=<HEX>`.
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "SECRET")]
+    pub webhook_hmac_secret: Option<String>,
+
+    /// Header name of the HMAC signature
+    #[clap(long, value_name = "NAME", default_value = "X-Signature-256")]
+    pub webhook_hmac_header: String,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct EmailArgs {
+    /// SMTP server host to send email.
+    #[clap(long, value_name = "HOST", requires_all = ["email_from", "email_to"])]
+    pub smtp_host: Option<String>,
+
+    /// SMTP server port
+    ///
+    /// If not specified, the default port of --smtp-tls will be used.
+    #[clap(long, value_name = "PORT")]
+    pub smtp_port: Option<u16>,
+
+    /// Security of the SMTP connection
+    #[clap(long, value_name = "TLS", value_enum, default_value = "starttls")]
+    pub smtp_tls: crate::email::SmtpTls,
+
+    /// SMTP user name for authentication
+    #[clap(long, value_name = "USER")]
+    pub smtp_user: Option<String>,
+
+    /// SMTP password for authentication
+    ///
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "PASSWORD")]
+    pub smtp_password: Option<String>,
+
+    /// Sender address (e.g. "Redmine <redmine@example.com>")
+    #[clap(long, value_name = "ADDRESS")]
+    pub email_from: Option<lettre::message::Mailbox>,
+
+    /// Recipient address
+    ///
+    /// If the filter expression is specified, only the matching updates are sent to the recipient.
+    /// It can be specified multiple times.
+    ///
+    /// e.g.) boss@example.com=priority in [High, Urgent]
+    #[clap(long, value_name = "ADDRESS[=EXPR]")]
+    pub email_to: Vec<crate::email::Recipient>,
+
+    /// Email digest mode
+    ///
+    /// Send updates of each run as one email per recipient.
+    #[clap(long)]
+    pub email_digest: bool,
+
+    /// Email plain-text template file path
+    ///
+    /// If not specified, the default template will be used.
+    #[clap(long, value_name = "PATH")]
+    pub email_text_template_path: Option<String>,
+
+    /// Email HTML template file path
+    ///
+    /// Values should be escaped. (e.g. escape filter of Tera)
+    /// If not specified, the default template will be used.
+    #[clap(long, value_name = "PATH")]
+    pub email_html_template_path: Option<String>,
+
+    /// Email digest plain-text template file path
+    ///
+    /// If not specified, the default template will be used.
+    #[clap(long, value_name = "PATH")]
+    pub email_digest_text_template_path: Option<String>,
+
+    /// Email digest HTML template file path
+    ///
+    /// If not specified, the default template will be used.
+    #[clap(long, value_name = "PATH")]
+    pub email_digest_html_template_path: Option<String>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct ExecArgs {
+    /// Command to run for each update
+    ///
+    /// It is run by the shell with the update as JSON on stdin,
+    /// and REDMINE_ISSUE_ID, REDMINE_EVENT and REDMINE_URL environment variables.
+    /// Non-zero exit status is treated as a delivery failure.
+    ///
+    /// e.g.) jq -r .new_issue.subject >> subjects.txt
+    #[clap(long, value_name = "COMMAND")]
+    pub exec: Option<String>,
+
+    /// Timeout of --exec
+    ///
+    /// The command is killed and treated as a failure after this duration.
+    #[clap(long, value_name = "DURATION", default_value = "30s", value_parser = humantime::parse_duration)]
+    pub exec_timeout: std::time::Duration,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct DeliveryArgs {
+    /// Quiet hours of the destination
+    ///
+    /// During quiet hours, updates are queued in the previous data file instead of sent,
+    /// then delivered as a single catch-up digest when the quiet hours end.
+    /// Specify the destination (e.g. slack) and the time range, and optionally the timezone.
+    /// If the timezone is not specified, the os local time will be used.
+    ///
+    /// e.g.) slack=22:00-07:00@Asia/Tokyo
+    #[clap(long, value_name = "DEST=HH:MM-HH:MM[@TZ]")]
+    pub quiet_hours: Vec<crate::schedule::QuietHours>,
+
+    /// Routing rule of the destination
+    ///
+    /// Only updates matching the filter expression are sent to the destination.
+    /// If specified multiple times for the same destination, any of them should match.
+    ///
+    /// e.g.) slack=tracker = Bug AND priority = Urgent
+    #[clap(long, value_name = "DEST=EXPR")]
+    pub route: Vec<crate::filter_expr::Route>,
+
+    /// Allow private issues and private notes to the destination
+    ///
+    /// By default, private issues and private notes are excluded. (e.g. slack)
+    #[clap(long, value_name = "DEST")]
+    pub allow_private: Vec<String>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct RetryArgs {
+    /// Number of retries when delivery failed
+    ///
+    /// Network errors, 429 Too Many Requests and 5xx server errors are retried.
+    #[clap(long, value_name = "NUM", default_value = "3")]
+    pub retry: u32,
+
+    /// Wait before the first retry
+    ///
+    /// It doubles on each retry. If the destination returns Retry-After, it is used instead.
+    #[clap(long, value_name = "DURATION", default_value = "1s", value_parser = humantime::parse_duration)]
+    pub retry_wait: std::time::Duration,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct RedactArgs {
+    /// Redaction rule applied to subject, description and contents before posting
+    ///
+    /// Specify <REGEX>=><REPLACEMENT>, or <REGEX> to replace with [REDACTED].
+    /// The replacement can refer to the capture groups such as $1.
+    ///
+    /// e.g.) (?i)(password:\s*)\S+=>${1}***
+    #[clap(long, value_name = "REGEX[=>REPLACEMENT]")]
+    pub redact: Vec<crate::redact::Rule>,
+
+    /// Built-in redaction rules separated by comma
+    ///
+    /// password, token, aws, github, slack, jwt, private-key, email, credit-card
+    #[clap(long, value_name = "PRESET(s)", value_delimiter = ',')]
+    pub redact_preset: Vec<String>,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct OpsArgs {
+    /// Ops channel incoming webhook URL to alert failures.
+    ///
+    /// An alert is posted after consecutive failed cycles, and a recovery message after the next success.
+    /// The payload is Slack compatible. ({"text": ...})
+    /// If you specify a file starting starts with @, it will read from that file.
+    /// If you specify a environment variable starting with $, it will read from that environment variable.
+    #[clap(long, value_name = "URL")]
+    pub ops_url: Option<String>,
+
+    /// Number of consecutive failed cycles to alert
+    #[clap(long, value_name = "NUM", default_value = "3", value_parser = clap::value_parser!(u32).range(1..))]
+    pub ops_failure_threshold: u32,
+}
+
+#[derive(Clone, Debug, clap::Parser)]
+pub struct LogArgs {
+    /// Minimum severity of logs
+    #[clap(long, value_enum, value_name = "LEVEL", default_value = "default")]
+    pub log_level: crate::log::Severity,
+
+    /// Verbose mode (same as --log-level debug)
+    #[clap(long)]
+    pub verbose: bool,
+
+    /// Log format
+    #[clap(long, value_enum, value_name = "FORMAT", default_value = "json")]
+    pub log_format: crate::log::Format,
+
+    /// Write logs to stderr instead of stdout
+    #[clap(long, conflicts_with = "log_file")]
+    pub log_stderr: bool,
+
+    /// Write logs to the file instead of stdout
+    ///
+    /// The file is rotated when it exceeds --log-file-size.
+    #[clap(long, value_name = "PATH")]
+    pub log_file: Option<String>,
+
+    /// Size in MiB to rotate the log file
+    #[clap(long, value_name = "MiB", default_value = "10")]
+    pub log_file_size: u64,
+
+    /// Number of rotated log files to keep
+    #[clap(long, value_name = "NUM", default_value = "5")]
+    pub log_file_count: u32,
 }
 
 pub fn parse() -> CliArgs {
     let mut cli_args = CliArgs::parse();
 
+    // Logging is initialized first to apply to the following normalization.
+    normalize_log(&mut cli_args.log, cli_args.jsonl.as_deref());
     normalize_redmine(&mut cli_args.redmine);
     normalize_slack(&mut cli_args.slack);
+    normalize_teams(&mut cli_args.teams);
+    normalize_discord(&mut cli_args.discord);
+    normalize_mattermost(&mut cli_args.mattermost);
+    normalize_webhook(&mut cli_args.webhook);
+    normalize_email(&mut cli_args.email, &cli_args.redmine);
+    normalize_delivery(&mut cli_args.delivery, &cli_args.redmine);
+    normalize_redact(&mut cli_args.redact);
+    normalize_ops(&mut cli_args.ops);
+    normalize_templates(&cli_args);
 
     cli_args
 }
 
+fn normalize_log(args: &mut LogArgs, jsonl: Option<&str>) {
+    if args.verbose {
+        args.log_level = crate::log::Severity::Debug;
+    }
+    let output = if let Some(path) = &args.log_file {
+        crate::log::Output::File(crate::log::RotatingFile::new(
+            path,
+            args.log_file_size * 1024 * 1024,
+            args.log_file_count,
+        ))
+    } else if args.log_stderr || jsonl == Some("-") {
+        // stdout is used for JSON Lines output.
+        crate::log::Output::Stderr
+    } else {
+        crate::log::Output::Stdout
+    };
+    crate::log::init(crate::log::Config {
+        level: args.log_level,
+        format: args.log_format,
+        output,
+    });
+}
+
 fn normalize_redmine(args: &mut RedmineArgs) {
     if args.subscribe_url.ends_with("/") {
         args.subscribe_url.pop();
@@ -117,9 +669,13 @@ fn normalize_redmine(args: &mut RedmineArgs) {
     normalize_filter(args).unwrap();
 }
 
+/// Read the value from a file with `@` or an environment variable with `$`.
+///
+/// The value is registered to be masked in logs, so it is only for credentials
+/// or values read from a file or an environment variable.
 fn normalize_secret(value: &str) -> anyhow::Result<String> {
     // If specified file
-    if value.starts_with("@") {
+    let value = if value.starts_with("@") {
         let path = value.trim_start_matches("@");
         let value = std::fs::read_to_string(path).unwrap_or_else(|e| {
             let msg = format!(
@@ -130,7 +686,7 @@ fn normalize_secret(value: &str) -> anyhow::Result<String> {
             crate::log::error(&msg).category("cli");
             std::process::exit(1);
         });
-        Ok(value)
+        value
     }
     // If specified environment variable
     else if value.starts_with("$") {
@@ -144,12 +700,15 @@ fn normalize_secret(value: &str) -> anyhow::Result<String> {
             crate::log::error(&msg).category("cli");
             std::process::exit(1);
         });
-        Ok(value)
+        value
     }
     // Otherwise
     else {
-        Ok(value.to_string())
-    }
+        value.to_string()
+    };
+    // Mask in logs and error messages.
+    crate::log::add_secret(&value);
+    Ok(value)
 }
 
 fn normalize_redmine_atom_key(args: &mut RedmineArgs) -> anyhow::Result<()> {
@@ -168,14 +727,30 @@ fn normalize_redmine_api_key(args: &mut RedmineArgs) -> anyhow::Result<()> {
 
 fn normalize_filter(args: &mut RedmineArgs) -> anyhow::Result<()> {
     if args.filter.target_project.is_some() && args.filter.ignore_project.is_some() {
-        let msg =
-            format!("Cannot specify both target_project and ignore_project at the same time.");
-        crate::log::error(&msg).category("cli");
+        let msg = "Cannot specify both target_project and ignore_project at the same time.";
+        crate::log::error(msg).category("cli");
         std::process::exit(1);
     }
     normalize_filter_target_project(args)?;
     normalize_filter_ignore_project(args)?;
     normalize_filter_user(args)?;
+    normalize_filter_assignee(args)?;
+    normalize_filter_ignore_author(args)?;
+    if let Some(expr) = &mut args.filter.filter_expr {
+        normalize_filter_expr(expr, &args.subscribe_url, &args.redmine_api_key)?;
+    }
+    Ok(())
+}
+
+fn normalize_filter_expr(
+    expr: &mut crate::filter_expr::Expr,
+    url: &str,
+    api_key: &Option<String>,
+) -> anyhow::Result<()> {
+    // If specified me, convert to id.
+    if expr.uses_me() {
+        expr.resolve_me(crate::redmine::get_current_user_id(url, api_key)?);
+    }
     Ok(())
 }
 
@@ -267,7 +842,165 @@ fn normalize_filter_user(args: &mut RedmineArgs) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn normalize_filter_assignee(args: &mut RedmineArgs) -> anyhow::Result<()> {
+    // If specified name or me, convert to id.
+    let mut map = None;
+    let mut current_user = None;
+    for list in [
+        args.filter.assignee.as_mut(),
+        args.filter.ignore_assignee.as_mut(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        for item in list {
+            if item.parse::<u32>().is_ok() {
+                continue;
+            }
+            if item == "me" {
+                if current_user.is_none() {
+                    current_user = Some(crate::redmine::get_current_user_id(
+                        &args.subscribe_url,
+                        &args.redmine_api_key,
+                    )?);
+                }
+                *item = current_user.unwrap().to_string();
+                continue;
+            }
+            if map.is_none() {
+                map = Some(crate::redmine::get_users_map(
+                    &args.subscribe_url,
+                    &args.redmine_api_key,
+                )?);
+            }
+            let map = map.as_ref().unwrap();
+            let id = map.get(item).unwrap_or_else(|| {
+                let msg = format!("Could not find user [{item}]", item = item);
+                crate::log::error(&msg).category("cli");
+                std::process::exit(1);
+            });
+            *item = id.to_string();
+        }
+    }
+    Ok(())
+}
+
+fn normalize_delivery(args: &mut DeliveryArgs, redmine: &RedmineArgs) {
+    let destinations = args
+        .quiet_hours
+        .iter()
+        .map(|q| &q.destination)
+        .chain(args.route.iter().map(|r| &r.destination))
+        .chain(args.allow_private.iter());
+    for destination in destinations {
+        if !crate::notifier::NAMES.contains(&destination.as_str()) {
+            let msg = format!(
+                "Unknown destination [{}] (expected one of {})",
+                destination,
+                crate::notifier::NAMES.join(", ")
+            );
+            crate::log::error(&msg).category("cli");
+            std::process::exit(1);
+        }
+    }
+    for route in &mut args.route {
+        normalize_filter_expr(
+            &mut route.expr,
+            &redmine.subscribe_url,
+            &redmine.redmine_api_key,
+        )
+        .unwrap();
+    }
+}
+
+fn normalize_filter_ignore_author(args: &mut RedmineArgs) -> anyhow::Result<()> {
+    if args.filter.ignore_own_changes {
+        args.filter
+            .ignore_author
+            .get_or_insert_with(Vec::new)
+            .push("me".to_string());
+    }
+    if args.filter.ignore_author.is_none() {
+        return Ok(());
+    }
+    // If specified name or me, convert to user id to compare with the journal author.
+    let mut map = None;
+    let mut ids = Vec::new();
+    for item in args.filter.ignore_author.as_ref().unwrap() {
+        let id = if item == "me" {
+            if args.redmine_api_key.is_none() {
+                let msg = "Cannot specify me for ignore_author without redmine_api_key.";
+                crate::log::error(msg).category("cli");
+                std::process::exit(1);
+            }
+            crate::redmine::get_current_user_id(&args.subscribe_url, &args.redmine_api_key)
+                .unwrap_or_else(|e| {
+                    let msg = format!("Could not get the user of redmine_api_key with {e}");
+                    crate::log::error(&msg).category("cli");
+                    std::process::exit(1);
+                })
+        } else if let Ok(id) = item.parse::<u32>() {
+            id
+        } else {
+            if map.is_none() {
+                map = Some(crate::redmine::get_users_map(
+                    &args.subscribe_url,
+                    &args.redmine_api_key,
+                )?);
+            }
+            let map = map.as_ref().unwrap();
+            *map.get(item).unwrap_or_else(|| {
+                let msg = format!("Could not find user [{item}]", item = item);
+                crate::log::error(&msg).category("cli");
+                std::process::exit(1);
+            })
+        };
+        ids.push(id.to_string());
+    }
+    args.filter.ignore_author = Some(ids);
+    Ok(())
+}
+
+fn normalize_redact(args: &mut RedactArgs) {
+    // Expand presets into rules.
+    for preset in &args.redact_preset {
+        let rules = crate::redact::preset(preset).unwrap_or_else(|e| {
+            crate::log::error(&e).category("cli");
+            std::process::exit(1);
+        });
+        args.redact.extend(rules);
+    }
+}
+
+/// Load the templates to report errors before the first cycle.
+fn normalize_templates(cli_args: &CliArgs) {
+    crate::template::Template::preload(
+        &[
+            &cli_args.slack.template_path,
+            &cli_args.slack.digest_template_path,
+            &cli_args.teams.teams_template_path,
+            &cli_args.mattermost.mattermost_template_path,
+            &cli_args.webhook.webhook_template_path,
+            &cli_args.email.email_text_template_path,
+            &cli_args.email.email_html_template_path,
+            &cli_args.email.email_digest_text_template_path,
+            &cli_args.email.email_digest_html_template_path,
+            &cli_args.jsonl_template_path,
+        ],
+        &cli_args.slack.template_dir,
+    );
+}
+
+fn normalize_ops(args: &mut OpsArgs) {
+    if let Some(ops_url) = &args.ops_url {
+        args.ops_url = Some(normalize_secret(ops_url).unwrap().trim().to_string());
+    }
+}
+
 fn normalize_slack(args: &mut SlackArgs) {
+    if args.digest_interval.is_some() {
+        args.digest = true;
+    }
     if args.notify_url.is_some() {
         let notify_url = args.notify_url.as_ref().unwrap();
         let mut notify_url = normalize_secret(notify_url).unwrap();
@@ -279,9 +1012,101 @@ fn normalize_slack(args: &mut SlackArgs) {
     }
 }
 
+fn normalize_teams(args: &mut TeamsArgs) {
+    if let Some(teams_url) = &args.teams_url {
+        args.teams_url = Some(normalize_secret(teams_url).unwrap().trim().to_string());
+    }
+}
+
+fn normalize_discord(args: &mut DiscordArgs) {
+    if let Some(discord_url) = &args.discord_url {
+        args.discord_url = Some(normalize_secret(discord_url).unwrap().trim().to_string());
+    }
+}
+
+fn normalize_mattermost(args: &mut MattermostArgs) {
+    if let Some(mattermost_url) = &args.mattermost_url {
+        args.mattermost_url = Some(normalize_secret(mattermost_url).unwrap().trim().to_string());
+    }
+}
+
+fn normalize_webhook(args: &mut WebhookArgs) {
+    if let Some(webhook_url) = &args.webhook_url {
+        args.webhook_url = Some(normalize_secret(webhook_url).unwrap().trim().to_string());
+    }
+    for header in &mut args.webhook_header {
+        header.value = normalize_header_value(&header.value).trim().to_string();
+    }
+    if let Some(secret) = &args.webhook_hmac_secret {
+        args.webhook_hmac_secret = Some(normalize_secret(secret).unwrap().trim().to_string());
+    }
+}
+
+/// Expand the header value.
+///
+/// Only the whole value of `@FILE` or `$VAR` is read from the file or the environment variable,
+/// and `${VAR}` in the value is replaced. Otherwise `$` and `@` are literal.
+fn normalize_header_value(value: &str) -> String {
+    let var_re = regex::Regex::new(r"^\$[A-Za-z_][A-Za-z0-9_]*$").unwrap();
+    if (value.starts_with('@') && 1 < value.len()) || var_re.is_match(value) {
+        return normalize_secret(value).unwrap();
+    }
+    let interpolation_re = regex::Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap();
+    interpolation_re
+        .replace_all(value, |c: &regex::Captures| {
+            normalize_secret(&format!("${}", &c[1])).unwrap()
+        })
+        .to_string()
+}
+
+fn normalize_email(args: &mut EmailArgs, redmine: &RedmineArgs) {
+    if let Some(smtp_password) = &args.smtp_password {
+        args.smtp_password = Some(normalize_secret(smtp_password).unwrap().trim().to_string());
+    }
+    for recipient in &mut args.email_to {
+        if let Some(expr) = &mut recipient.expr {
+            normalize_filter_expr(expr, &redmine.subscribe_url, &redmine.redmine_api_key).unwrap();
+        }
+    }
+}
+
 // fn normalize_slack_oauth_token(args: &mut SlackArgs) -> anyhow::Result<()> {
 //     if let Some(slack_oauth_token) = &args.slack_oauth_token {
 //         args.slack_oauth_token = Some(normalize_secret(slack_oauth_token)?);
 //     }
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn header_value_expands_only_explicit_references() {
+        std::env::set_var("R2S_TEST_HEADER_TOKEN", "t0ken-value");
+        let expand = super::normalize_header_value;
+        assert_eq!(expand("$R2S_TEST_HEADER_TOKEN"), "t0ken-value");
+        assert_eq!(
+            expand("Bearer ${R2S_TEST_HEADER_TOKEN}"),
+            "Bearer t0ken-value"
+        );
+        assert_eq!(
+            expand("Bearer $R2S_TEST_HEADER_TOKEN"),
+            "Bearer $R2S_TEST_HEADER_TOKEN"
+        );
+        assert_eq!(expand("$5 off"), "$5 off");
+        assert_eq!(expand("application/json"), "application/json");
+    }
+
+    #[test]
+    fn plain_header_value_is_not_redacted() {
+        super::normalize_header_value("production");
+        super::normalize_header_value("application/json");
+        assert_eq!(
+            crate::log::redact("Posted to production with Content-Type: application/json"),
+            "Posted to production with Content-Type: application/json"
+        );
+
+        std::env::set_var("R2S_TEST_HEADER_SECRET", "s3cret-from-env");
+        let value = super::normalize_header_value("Token ${R2S_TEST_HEADER_SECRET}");
+        assert_eq!(crate::log::redact(&value), "Token [REDACTED]");
+    }
+}
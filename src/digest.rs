@@ -0,0 +1,268 @@
+// Copyright © ArkBig
+//! This file provides digest that batches updates into one summary message.
+
+use crate::redmine::UpdateInfo;
+
+/// Updates waiting for the digest delivery.
+#[derive(serde::Serialize, serde::Deserialize, Default)]
+pub struct Pending {
+    /// The time when the first update was queued.
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    updates: Vec<UpdateInfo>,
+}
+
+impl Pending {
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Queue the updates.
+    ///
+    /// If the same issue is already queued, it is merged into one.
+    pub fn extend(&mut self, updates: Vec<UpdateInfo>) {
+        if updates.is_empty() {
+            return;
+        }
+        if self.since.is_none() {
+            self.since = Some(chrono::Utc::now());
+        }
+        for update in updates {
+            let queued = self
+                .updates
+                .iter_mut()
+                .find(|u| u.new_issue["id"] == update.new_issue["id"]);
+            let Some(queued) = queued else {
+                self.updates.push(update);
+                continue;
+            };
+            // Keep the oldest values of the changed items.
+            if let (Some(old), Some(new)) = (
+                queued.old_items.as_object_mut(),
+                update.old_items.as_object(),
+            ) {
+                for (key, value) in new {
+                    if old.get(key).map_or(true, |v| v.is_null()) {
+                        old.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            for field in update.changed_fields {
+                if !queued.changed_fields.contains(&field) {
+                    queued.changed_fields.push(field);
+                }
+            }
+            for author in update.authors {
+                if !queued.authors.contains(&author) {
+                    queued.authors.push(author);
+                }
+            }
+            queued.update_contents.extend(update.update_contents);
+            queued.private_notes_skipped += update.private_notes_skipped;
+            queued.new_issue = update.new_issue;
+            queued.local_updated_time = update.local_updated_time;
+            // An issue created in the window is still a new issue.
+            if queued.event != crate::redmine::EventKind::Created {
+                queued.event = update.event;
+            }
+        }
+    }
+
+    /// Whether the digest window has passed.
+    ///
+    /// If the interval is not specified, it is always due. (i.e. per run)
+    pub fn is_due(&self, interval: Option<std::time::Duration>) -> bool {
+        match (self.since, interval) {
+            (_, None) => true,
+            (None, Some(_)) => false,
+            (Some(since), Some(interval)) => since + interval <= chrono::Utc::now(),
+        }
+    }
+
+    /// The queued updates.
+    pub fn updates(&self) -> &[UpdateInfo] {
+        &self.updates
+    }
+
+    /// Take out the queued updates.
+    pub fn take(&mut self) -> Vec<UpdateInfo> {
+        self.since = None;
+        std::mem::take(&mut self.updates)
+    }
+}
+
+/// Digest message context for template
+#[derive(serde::Serialize)]
+pub struct Digest {
+    pub since: chrono::DateTime<chrono::FixedOffset>,
+    pub until: chrono::DateTime<chrono::FixedOffset>,
+    pub count: usize,
+    /// Whether this is the catch-up after the quiet hours.
+    pub catch_up: bool,
+    pub projects: Vec<DigestProject>,
+}
+
+#[derive(serde::Serialize)]
+pub struct DigestProject {
+    pub name: String,
+    pub updates: Vec<UpdateInfo>,
+}
+
+impl Digest {
+    /// Group the updates by project. (Each group is sorted by updated time.)
+    pub fn new(mut updates: Vec<UpdateInfo>) -> Option<Self> {
+        updates.sort_by_key(|u| u.local_updated_time);
+        let since = updates.first()?.local_updated_time;
+        let until = updates.last()?.local_updated_time;
+        let count = updates.len();
+        let mut projects: Vec<DigestProject> = Vec::new();
+        for update in updates {
+            let name = update.new_issue["project"]["name"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            if let Some(project) = projects.iter_mut().find(|p| p.name == name) {
+                project.updates.push(update);
+            } else {
+                projects.push(DigestProject {
+                    name,
+                    updates: vec![update],
+                });
+            }
+        }
+        Some(Digest {
+            since,
+            until,
+            count,
+            catch_up: false,
+            projects,
+        })
+    }
+
+    /// Heading such as `Redmine digest 3 updates (01/02 03:04 - 01/02 05:06)`.
+    pub fn heading(&self) -> String {
+        format!(
+            "{} {} updates ({} - {})",
+            if self.catch_up {
+                "Redmine catch-up"
+            } else {
+                "Redmine digest"
+            },
+            self.count,
+            self.since.format("%m/%d %H:%M"),
+            self.until.format("%m/%d %H:%M")
+        )
+    }
+}
+
+/// One line of the update in Markdown for the chat sinks. (same items as the Slack digest)
+pub fn markdown_line(update: &UpdateInfo) -> String {
+    let issue = &update.new_issue;
+    let tracker = crate::notifier::name_of(&issue["tracker"]);
+    format!(
+        "• {}[#{}]({}) {} [{}] {} {}",
+        if tracker.is_empty() {
+            tracker
+        } else {
+            tracker + " "
+        },
+        issue["id"],
+        update.url,
+        crate::notifier::name_of(&issue["subject"]),
+        crate::notifier::name_of(&issue["status"]),
+        update.event.as_str(),
+        update.local_updated_time.format("%H:%M")
+    )
+}
+
+/// Digest in Markdown, which is the heading and the lines grouped by project.
+pub fn to_markdown(digest: &Digest) -> String {
+    let mut text = format!("**{}**\n", digest.heading());
+    for project in &digest.projects {
+        text.push_str(&format!("\n**{}**\n", project.name));
+        for update in &project.updates {
+            text.push_str(&markdown_line(update));
+            text.push('\n');
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    fn update(
+        event: &str,
+        status: &str,
+        old_status: Option<&str>,
+        skipped: usize,
+    ) -> super::UpdateInfo {
+        serde_json::from_value(serde_json::json!({
+            "url": "https://redmine.example.com/issues/1",
+            "event": event,
+            "local_updated_time": "2024-01-02T03:04:05+09:00",
+            "new_issue": { "id": 1, "status": { "name": status } },
+            "old_items": { "status": old_status.map(|name| serde_json::json!({ "name": name })) },
+            "changed_fields": ["status"],
+            "authors": [status],
+            "update_contents": [{ "author": status, "content": format!("to {}", status) }],
+            "private_notes_skipped": skipped,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn merges_updates_of_the_same_issue() {
+        let mut pending = super::Pending::default();
+        pending.extend(vec![update("created", "New", None, 1)]);
+        pending.extend(vec![update("updated", "Open", Some("New"), 2)]);
+        pending.extend(vec![update("closed", "Closed", Some("Open"), 0)]);
+
+        let updates = pending.take();
+        assert_eq!(updates.len(), 1);
+        let merged = &updates[0];
+        assert_eq!(merged.event, crate::redmine::EventKind::Created);
+        assert_eq!(merged.new_issue["status"]["name"], "Closed");
+        // The oldest value is kept.
+        assert_eq!(merged.old_items["status"]["name"], "New");
+        assert_eq!(merged.authors, vec!["New", "Open", "Closed"]);
+        assert_eq!(merged.update_contents.len(), 3);
+        assert_eq!(merged.private_notes_skipped, 3);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_latest_event_of_existing_issue() {
+        let mut pending = super::Pending::default();
+        pending.extend(vec![update("updated", "Open", Some("New"), 0)]);
+        pending.extend(vec![update("closed", "Closed", Some("Open"), 0)]);
+        assert_eq!(pending.take()[0].event, crate::redmine::EventKind::Closed);
+    }
+
+    #[test]
+    fn markdown_groups_lines_by_project() {
+        let mut update: super::UpdateInfo = serde_json::from_value(serde_json::json!({
+            "url": "https://redmine.example.com/issues/1",
+            "event": "updated",
+            "local_updated_time": "2024-01-02T03:04:05+09:00",
+            "new_issue": {
+                "id": 1,
+                "subject": "Crash",
+                "project": { "name": "App" },
+                "tracker": { "name": "Bug" },
+                "status": { "name": "Open" },
+            },
+            "old_items": {},
+            "update_contents": [],
+        }))
+        .unwrap();
+        let mut digest = super::Digest::new(vec![update.clone()]).unwrap();
+        digest.catch_up = true;
+        assert_eq!(
+            super::to_markdown(&digest),
+            "**Redmine catch-up 1 updates (01/02 03:04 - 01/02 03:04)**\n\n**App**\n\
+             • Bug [#1](https://redmine.example.com/issues/1) Crash [Open] updated 03:04\n"
+        );
+
+        update.new_issue["tracker"] = serde_json::Value::Null;
+        assert!(super::markdown_line(&update).starts_with("• [#1]("));
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright © ArkBig
+//! This file provides notification to Discord with embeds.
+//!
+//! Embeds are truncated to fit the limits of Discord.
+//! (see <https://discord.com/developers/docs/resources/message#embed-object-embed-limits>)
+
+const TITLE_LIMIT: usize = 256;
+const DESCRIPTION_LIMIT: usize = 4096;
+const FIELD_VALUE_LIMIT: usize = 1024;
+const AUTHOR_LIMIT: usize = 256;
+const FOOTER_LIMIT: usize = 2048;
+/// Limit of the sum of all characters in the embed.
+const TOTAL_LIMIT: usize = 6000;
+
+/// Default colors for the default priorities of Redmine.
+const DEFAULT_COLORS: [(&str, u32); 5] = [
+    ("low", 0x95a5a6),
+    ("normal", 0x3498db),
+    ("high", 0xe67e22),
+    ("urgent", 0xe74c3c),
+    ("immediate", 0x992d22),
+];
+
+/// Embed color of the priority
+///
+/// Format: `<PRIORITY>=<#RRGGBB>` (e.g. `Urgent=#ff0000`). The priority is name or number.
+#[derive(Clone, Debug)]
+pub struct PriorityColor {
+    pub priority: String,
+    pub color: u32,
+}
+
+impl std::str::FromStr for PriorityColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (priority, color) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <PRIORITY>=<#RRGGBB>, but [{s}]"))?;
+        let color = u32::from_str_radix(color.trim().trim_start_matches('#'), 16)
+            .map_err(|e| format!("Invalid color [{color}] with {e}"))?;
+        Ok(PriorityColor {
+            priority: priority.trim().to_string(),
+            color,
+        })
+    }
+}
+
+/// Discord webhook
+pub struct Discord<'a> {
+    args: &'a crate::cli_args::DiscordArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    notify_url: &'a str,
+}
+
+impl<'a> Discord<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::DiscordArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        notify_url: &'a str,
+    ) -> Self {
+        Discord {
+            args,
+            retry,
+            notify_url,
+        }
+    }
+}
+
+impl crate::notifier::Notifier for Discord<'_> {
+    fn name(&self) -> &str {
+        "discord"
+    }
+
+    /// Render the update as an embed.
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        let embed = convert_to_embed(&self.args.discord_priority_color, update);
+        Ok(serde_json::json!({ "embeds": [embed] }))
+    }
+
+    fn capabilities(&self) -> crate::notifier::Capabilities {
+        crate::notifier::Capabilities { digest: true }
+    }
+
+    /// Render the digest as one embed. (used for the catch-up after the quiet hours)
+    fn render_digest(&self, digest: &crate::digest::Digest) -> anyhow::Result<serde_json::Value> {
+        let description = digest
+            .projects
+            .iter()
+            .map(|project| {
+                let lines = project
+                    .updates
+                    .iter()
+                    .map(crate::digest::markdown_line)
+                    .collect::<Vec<_>>();
+                format!("**{}**\n{}", project.name, lines.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let embed = serde_json::json!({
+            "title": truncate(&digest.heading(), TITLE_LIMIT),
+            "description": truncate(&description, DESCRIPTION_LIMIT),
+            "timestamp": digest.until.to_rfc3339(),
+        });
+        Ok(serde_json::json!({ "embeds": [embed] }))
+    }
+
+    /// If the rate limit is reached, it waits until the reset before returning.
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let res = crate::webhook::post_json(self.retry, self.notify_url, payload, "discord")?;
+        wait_rate_limit(&res);
+        Ok(())
+    }
+}
+
+/// Wait until the rate limit bucket is reset if no requests remain.
+fn wait_rate_limit(res: &ureq::Response) {
+    let remaining = res
+        .header("X-RateLimit-Remaining")
+        .and_then(|v| v.trim().parse::<u32>().ok());
+    let reset_after = res
+        .header("X-RateLimit-Reset-After")
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|secs| secs.is_finite() && 0.0 < *secs);
+    if let (Some(0), Some(reset_after)) = (remaining, reset_after) {
+        let fields = serde_json::json!({ "sink": "discord", "wait": reset_after });
+        crate::log::debug("Rate limited.")
+            .category("discord")
+            .json(&fields);
+        std::thread::sleep(std::time::Duration::from_secs_f64(reset_after));
+    }
+}
+
+fn convert_to_embed(
+    colors: &[PriorityColor],
+    update: &crate::redmine::UpdateInfo,
+) -> serde_json::Value {
+    let issue = &update.new_issue;
+    let title = truncate(
+        &format!(
+            "#{} {}",
+            issue["id"],
+            crate::notifier::name_of(&issue["subject"])
+        ),
+        TITLE_LIMIT,
+    );
+    let author = truncate(&update.authors.join(", "), AUTHOR_LIMIT);
+    let footer = truncate(
+        &[
+            crate::notifier::name_of(&issue["project"]),
+            crate::notifier::name_of(&issue["tracker"]),
+            update
+                .local_updated_time
+                .format("%Y-%m-%d %H:%M")
+                .to_string(),
+        ]
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" • "),
+        FOOTER_LIMIT,
+    );
+
+    // Fields with the old values
+    let fields = crate::notifier::field_changes(update)
+        .into_iter()
+        .map(|(name, value)| (name, truncate(&value, FIELD_VALUE_LIMIT)))
+        .collect::<Vec<_>>();
+
+    // Notes fill the rest of the total limit.
+    let used = title.chars().count()
+        + author.chars().count()
+        + footer.chars().count()
+        + fields
+            .iter()
+            .map(|(name, value)| name.chars().count() + value.chars().count())
+            .sum::<usize>();
+    let mut description = update
+        .update_contents
+        .iter()
+        .map(|c| format!("**{}**\n{}", c.author, c.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if 0 < update.private_notes_skipped {
+        description.push_str(&format!(
+            "\n\n*({} private notes hidden)*",
+            update.private_notes_skipped
+        ));
+    }
+    let description = truncate(
+        description.trim(),
+        DESCRIPTION_LIMIT.min(TOTAL_LIMIT.saturating_sub(used)),
+    );
+
+    let mut embed = serde_json::json!({
+        "title": title,
+        "url": update.url,
+        "color": color_of(colors, &issue["priority"]),
+        "fields": fields
+            .iter()
+            .map(|(name, value)| serde_json::json!({ "name": name, "value": value, "inline": true }))
+            .collect::<Vec<_>>(),
+        "footer": { "text": footer },
+        "timestamp": update.local_updated_time.to_rfc3339(),
+    });
+    if !author.is_empty() {
+        embed["author"] = serde_json::json!({ "name": author });
+    }
+    if !description.is_empty() {
+        embed["description"] = serde_json::json!(description);
+    }
+    embed
+}
+
+/// Color by the priority name or id.
+fn color_of(colors: &[PriorityColor], priority: &serde_json::Value) -> u32 {
+    let name = crate::notifier::name_of(priority);
+    let id = priority["id"].to_string();
+    if let Some(c) = colors
+        .iter()
+        .find(|c| c.priority.eq_ignore_ascii_case(&name) || c.priority == id)
+    {
+        return c.color;
+    }
+    DEFAULT_COLORS
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(&name))
+        .map_or(0x95a5a6, |(_, color)| *color)
+}
+
+/// Truncate to `max` characters including the trailing `…`.
+fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    if max == 0 {
+        return String::new();
+    }
+    let mut s = text.chars().take(max - 1).collect::<String>();
+    s.push('…');
+    s
+}
@@ -0,0 +1,411 @@
+// Copyright © ArkBig
+//! This file provides notification by email (SMTP).
+//!
+//! Each update is sent as one message with HTML and plain-text parts to all matching recipients,
+//! so that a failure does not leave some recipients delivered and the others not.
+
+/// Recipient of the email
+///
+/// Format: `<ADDRESS>[=<EXPR>]`. If the filter expression is specified,
+/// only the matching updates are sent to the recipient. (e.g. `boss@example.com=priority = Urgent`)
+#[derive(Clone, Debug)]
+pub struct Recipient {
+    pub address: lettre::message::Mailbox,
+    pub expr: Option<crate::filter_expr::Expr>,
+}
+
+impl std::str::FromStr for Recipient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, expr) = match s.split_once('=') {
+            Some((address, expr)) => (
+                address,
+                Some(
+                    expr.parse()
+                        .map_err(|e: crate::filter_expr::ParseError| e.to_string())?,
+                ),
+            ),
+            None => (s, None),
+        };
+        Ok(Recipient {
+            address: address
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid address [{address}] with {e}"))?,
+            expr,
+        })
+    }
+}
+
+impl Recipient {
+    fn matches(&self, update: &crate::redmine::UpdateInfo) -> bool {
+        self.expr.as_ref().map_or(true, |expr| expr.eval(update))
+    }
+}
+
+/// Security of the SMTP connection
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum SmtpTls {
+    /// Upgrade with STARTTLS. (default port 587)
+    Starttls,
+    /// Implicit TLS. (default port 465)
+    Tls,
+    /// No encryption. (default port 25, e.g. for local SMTP server)
+    None,
+}
+
+/// Email by SMTP
+pub struct Email<'a> {
+    args: &'a crate::cli_args::EmailArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    host: &'a str,
+    from: &'a lettre::message::Mailbox,
+}
+
+impl<'a> Email<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::EmailArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        host: &'a str,
+        from: &'a lettre::message::Mailbox,
+    ) -> Self {
+        Email {
+            args,
+            retry,
+            host,
+            from,
+        }
+    }
+
+    fn transport(&self) -> anyhow::Result<lettre::SmtpTransport> {
+        let builder = match self.args.smtp_tls {
+            SmtpTls::Starttls => lettre::SmtpTransport::starttls_relay(self.host)?,
+            SmtpTls::Tls => lettre::SmtpTransport::relay(self.host)?,
+            SmtpTls::None => lettre::SmtpTransport::builder_dangerous(self.host),
+        };
+        let mut builder = builder.timeout(Some(std::time::Duration::from_secs(30)));
+        if let Some(port) = self.args.smtp_port {
+            builder = builder.port(port);
+        }
+        if let Some(user) = &self.args.smtp_user {
+            builder =
+                builder.credentials(lettre::transport::smtp::authentication::Credentials::new(
+                    user.clone(),
+                    self.args.smtp_password.clone().unwrap_or_default(),
+                ));
+        }
+        Ok(builder.build())
+    }
+
+    /// Send the message with retry for transient errors.
+    fn send(
+        &self,
+        transport: &lettre::SmtpTransport,
+        message: &lettre::Message,
+    ) -> anyhow::Result<()> {
+        use lettre::Transport as _;
+        let mut attempt = 0;
+        loop {
+            let err = match transport.send(message) {
+                Ok(_) => return Ok(()),
+                Err(err) if err.is_permanent() => return Err(err.into()),
+                Err(err) => err,
+            };
+            if self.retry.retry <= attempt {
+                return Err(err.into());
+            }
+            let wait = crate::webhook::backoff(self.retry, attempt);
+            attempt += 1;
+            crate::metrics::inc_retry("email");
+            let fields = crate::webhook::retry_fields("email", attempt, wait);
+            crate::log::warning(&format!("Retry sending with {}", err))
+                .category("email")
+                .json(&fields);
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+impl crate::notifier::Notifier for Email<'_> {
+    fn name(&self) -> &str {
+        "email"
+    }
+
+    fn capabilities(&self) -> crate::notifier::Capabilities {
+        crate::notifier::Capabilities { digest: true }
+    }
+
+    fn digest_mode(&self) -> crate::notifier::DigestMode {
+        crate::notifier::DigestMode {
+            enabled: self.args.email_digest,
+            interval: None,
+        }
+    }
+
+    /// Render the update as one message to the matching recipients.
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        let to = self
+            .args
+            .email_to
+            .iter()
+            .filter(|r| r.matches(update))
+            .map(|r| r.address.to_string())
+            .collect::<Vec<_>>();
+        if to.is_empty() {
+            return Ok(serde_json::json!({ "messages": [] }));
+        }
+        let text = crate::template::Template::load(
+            &self.args.email_text_template_path,
+            "email-notification",
+            include_str!("../resources/email-notification.template"),
+        )
+        .render(update)?;
+        let html = crate::template::Template::load(
+            &self.args.email_html_template_path,
+            "email-notification.html.tera",
+            include_str!("../resources/email-notification.html.tera"),
+        )
+        .render(update)?;
+        let subject = format!(
+            "[{}] #{} {} ({})",
+            crate::notifier::name_of(&update.new_issue["project"]),
+            update.new_issue["id"],
+            crate::notifier::name_of(&update.new_issue["subject"]),
+            update.event.as_str()
+        );
+        Ok(serde_json::json!({
+            "messages": [{ "to": to, "subject": subject, "text": text, "html": html }],
+        }))
+    }
+
+    /// Render the digest for each recipient with the matching updates.
+    fn render_digest(&self, digest: &crate::digest::Digest) -> anyhow::Result<serde_json::Value> {
+        let text_template = crate::template::Template::load(
+            &self.args.email_digest_text_template_path,
+            "email-digest",
+            include_str!("../resources/email-digest.template"),
+        );
+        let html_template = crate::template::Template::load(
+            &self.args.email_digest_html_template_path,
+            "email-digest.html.tera",
+            include_str!("../resources/email-digest.html.tera"),
+        );
+        let mut messages = Vec::new();
+        for recipient in &self.args.email_to {
+            let updates = digest
+                .projects
+                .iter()
+                .flat_map(|p| p.updates.iter())
+                .filter(|u| recipient.matches(u))
+                .cloned()
+                .collect::<Vec<_>>();
+            let Some(mut own) = crate::digest::Digest::new(updates) else {
+                continue;
+            };
+            own.catch_up = digest.catch_up;
+            let subject = format!(
+                "[Redmine] {} {} updates",
+                if own.catch_up { "Catch-up" } else { "Digest" },
+                own.count
+            );
+            let message = serde_json::json!({
+                "subject": subject,
+                "text": text_template.render(&own)?,
+                "html": html_template.render(&own)?,
+            });
+            // Recipients of the same digest share one message.
+            let address = serde_json::json!(recipient.address.to_string());
+            let same = messages.iter_mut().find(|m: &&mut serde_json::Value| {
+                ["subject", "text", "html"]
+                    .iter()
+                    .all(|key| m[key] == message[key])
+            });
+            match same {
+                Some(same) => same["to"].as_array_mut().unwrap().push(address),
+                None => {
+                    let mut message = message;
+                    message["to"] = serde_json::json!([address]);
+                    messages.push(message);
+                }
+            }
+        }
+        Ok(serde_json::json!({ "messages": messages }))
+    }
+
+    /// Send each message to all its recipients at once.
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let messages = payload["messages"].as_array().cloned().unwrap_or_default();
+        if messages.is_empty() {
+            return Ok(());
+        }
+        let transport = self.transport()?;
+        for message in messages {
+            let mut builder = lettre::Message::builder().from(self.from.clone());
+            for to in message["to"].as_array().cloned().unwrap_or_default() {
+                builder = builder.to(to.as_str().unwrap_or_default().parse()?);
+            }
+            let email = builder
+                .subject(message["subject"].as_str().unwrap_or_default())
+                .multipart(lettre::message::MultiPart::alternative_plain_html(
+                    message["text"].as_str().unwrap_or_default().to_string(),
+                    message["html"].as_str().unwrap_or_default().to_string(),
+                ))?;
+            self.send(&transport, &email)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::notifier::Notifier as _;
+    use clap::Parser as _;
+    use std::io::{BufRead as _, Write as _};
+
+    /// Mail transaction received by the stub SMTP server.
+    #[derive(Debug, Default)]
+    struct Transaction {
+        rcpt: Vec<String>,
+        data: String,
+    }
+
+    /// Start a stub SMTP server that rejects the recipients containing `reject`.
+    fn start_smtp() -> (u16, std::sync::Arc<std::sync::Mutex<Vec<Transaction>>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let shared = received.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+                let mut transaction = Transaction::default();
+                let mut reply = |s: &str| stream.write_all(format!("{}\r\n", s).as_bytes());
+                reply("220 localhost").unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or_default() != 0 {
+                    let command = line.trim_end().to_ascii_uppercase();
+                    let response = if command.starts_with("EHLO") || command.starts_with("MAIL") {
+                        "250 OK"
+                    } else if command.starts_with("RCPT") && command.contains("REJECT") {
+                        "550 No such user"
+                    } else if command.starts_with("RCPT") {
+                        transaction.rcpt.push(line.trim_end()[8..].to_string());
+                        "250 OK"
+                    } else if command == "DATA" {
+                        reply("354 Start mail input").unwrap();
+                        let mut data = String::new();
+                        while reader.read_line(&mut data).unwrap() != 0
+                            && !data.ends_with("\r\n.\r\n")
+                        {}
+                        transaction.data = data;
+                        shared
+                            .lock()
+                            .unwrap()
+                            .push(std::mem::take(&mut transaction));
+                        "250 OK"
+                    } else if command == "QUIT" {
+                        reply("221 Bye").unwrap();
+                        break;
+                    } else {
+                        "250 OK"
+                    };
+                    reply(response).unwrap();
+                    line.clear();
+                }
+            }
+        });
+        (port, received)
+    }
+
+    fn args(port: u16, to: &[&str], digest: bool) -> crate::cli_args::EmailArgs {
+        let mut args = vec![
+            "test".to_string(),
+            "--smtp-host=127.0.0.1".to_string(),
+            format!("--smtp-port={}", port),
+            "--smtp-tls=none".to_string(),
+            "--email-from=redmine@example.com".to_string(),
+        ];
+        args.extend(to.iter().map(|to| format!("--email-to={}", to)));
+        if digest {
+            args.push("--email-digest".to_string());
+        }
+        crate::cli_args::EmailArgs::parse_from(args)
+    }
+
+    fn update(id: u64, priority: &str) -> crate::redmine::UpdateInfo {
+        serde_json::from_value(serde_json::json!({
+            "url": format!("https://redmine.example.com/issues/{}", id),
+            "event": "updated",
+            "local_updated_time": "2024-01-02T03:04:05+09:00",
+            "new_issue": {
+                "id": id,
+                "subject": "Crash on save",
+                "project": { "id": 1, "name": "App" },
+                "priority": { "id": 2, "name": priority },
+            },
+            "old_items": {},
+            "update_contents": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn sends_one_message_to_all_recipients() {
+        let (port, received) = start_smtp();
+        let args = args(port, &["a@example.com", "b@example.com"], false);
+        let retry = crate::cli_args::RetryArgs::parse_from(["test", "--retry=0"]);
+        let from = args.email_from.clone().unwrap();
+        let email = super::Email::new(&args, &retry, "127.0.0.1", &from);
+
+        let payload = email.render(&update(1, "Normal")).unwrap();
+        email.deliver(&payload).unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].rcpt, vec!["<a@example.com>", "<b@example.com>"]);
+        assert!(received[0]
+            .data
+            .contains("Subject: [App] #1 Crash on save (updated)"));
+    }
+
+    #[test]
+    fn sends_nothing_if_a_recipient_is_rejected() {
+        let (port, received) = start_smtp();
+        let args = args(port, &["a@example.com", "reject@example.com"], false);
+        let retry = crate::cli_args::RetryArgs::parse_from(["test", "--retry=0"]);
+        let from = args.email_from.clone().unwrap();
+        let email = super::Email::new(&args, &retry, "127.0.0.1", &from);
+
+        let payload = email.render(&update(1, "Normal")).unwrap();
+        assert!(email.deliver(&payload).is_err());
+        // Nobody received it, so the retry in the next run does not duplicate it.
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn shares_the_same_digest() {
+        let (port, received) = start_smtp();
+        let args = args(
+            port,
+            &[
+                "a@example.com",
+                "b@example.com",
+                "boss@example.com=priority = Urgent",
+            ],
+            true,
+        );
+        let retry = crate::cli_args::RetryArgs::parse_from(["test", "--retry=0"]);
+        let from = args.email_from.clone().unwrap();
+        let email = super::Email::new(&args, &retry, "127.0.0.1", &from);
+
+        let digest =
+            crate::digest::Digest::new(vec![update(1, "Normal"), update(2, "Urgent")]).unwrap();
+        let payload = email.render_digest(&digest).unwrap();
+        email.deliver(&payload).unwrap();
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].rcpt, vec!["<a@example.com>", "<b@example.com>"]);
+        assert_eq!(received[1].rcpt, vec!["<boss@example.com>"]);
+    }
+}
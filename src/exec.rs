@@ -0,0 +1,210 @@
+// Copyright © ArkBig
+//! This file provides running a local command for each update.
+//!
+//! The update is written to stdin of the command as JSON,
+//! and the exit status is treated as success or failure of the delivery.
+
+/// Local command hook
+pub struct Exec<'a> {
+    args: &'a crate::cli_args::ExecArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    command: &'a str,
+}
+
+impl<'a> Exec<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::ExecArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        command: &'a str,
+    ) -> Self {
+        Exec {
+            args,
+            retry,
+            command,
+        }
+    }
+
+    /// Run the command once and wait for the exit within the timeout.
+    fn run(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let mut command = if cfg!(windows) {
+            let mut command = std::process::Command::new("cmd");
+            command.arg("/C").arg(self.command);
+            command
+        } else {
+            let mut command = std::process::Command::new("sh");
+            command.arg("-c").arg(self.command);
+            command
+        };
+        command
+            .env("REDMINE_ISSUE_ID", payload["new_issue"]["id"].to_string())
+            .env(
+                "REDMINE_EVENT",
+                payload["event"].as_str().unwrap_or_default(),
+            )
+            .env("REDMINE_URL", payload["url"].as_str().unwrap_or_default())
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped());
+        let mut child = command.spawn()?;
+
+        // Read outputs in background not to block the command by the full pipe.
+        let stdout = read_in_background(child.stdout.take());
+        let stderr = read_in_background(child.stderr.take());
+        if let Some(mut stdin) = child.stdin.take() {
+            let input = payload.to_string();
+            std::thread::spawn(move || {
+                use std::io::Write as _;
+                // The command may exit without reading stdin.
+                let _ = stdin.write_all(input.as_bytes());
+            });
+        }
+
+        let deadline = std::time::Instant::now() + self.args.exec_timeout;
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if deadline <= std::time::Instant::now() {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        };
+        let Some(status) = status else {
+            // Outputs are not waited because subprocesses may still hold them.
+            anyhow::bail!(
+                "Command timed out after {}",
+                humantime::format_duration(self.args.exec_timeout)
+            );
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        let fields = serde_json::json!({
+            "sink": "exec",
+            "issue_id": payload["new_issue"]["id"],
+            "status": status.to_string(),
+            "stdout": stdout.trim(),
+            "stderr": stderr.trim(),
+        });
+        crate::log::debug("Command finished.")
+            .category("exec")
+            .json(&fields);
+
+        if !status.success() {
+            anyhow::bail!("Command failed with {}: {}", status, stderr.trim());
+        }
+        Ok(())
+    }
+}
+
+fn read_in_background<R: std::io::Read + Send + 'static>(
+    reader: Option<R>,
+) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut s = String::new();
+        if let Some(mut reader) = reader {
+            let _ = reader.read_to_string(&mut s);
+        }
+        s
+    })
+}
+
+impl crate::notifier::Notifier for Exec<'_> {
+    fn name(&self) -> &str {
+        "exec"
+    }
+
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::to_value(update)?)
+    }
+
+    /// Run the command, and retry if it failed.
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let mut attempt = 0;
+        loop {
+            let err = match self.run(payload) {
+                Ok(()) => return Ok(()),
+                Err(err) => err,
+            };
+            if self.retry.retry <= attempt {
+                return Err(err);
+            }
+            let wait = crate::webhook::backoff(self.retry, attempt);
+            attempt += 1;
+            crate::metrics::inc_retry("exec");
+            let fields = crate::webhook::retry_fields("exec", attempt, wait);
+            crate::log::warning(&format!("Retry command with {}", err))
+                .category("exec")
+                .json(&fields);
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::notifier::Notifier as _;
+    use clap::Parser as _;
+
+    fn payload() -> serde_json::Value {
+        serde_json::json!({
+            "url": "https://redmine.example.com/issues/1",
+            "event": "updated",
+            "new_issue": { "id": 1, "subject": "Crash on save" },
+        })
+    }
+
+    fn exec_args(timeout: &str) -> crate::cli_args::ExecArgs {
+        crate::cli_args::ExecArgs::parse_from(["test", "--exec-timeout", timeout])
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("exec-{}-{}", name, std::process::id()))
+    }
+
+    #[test]
+    fn non_zero_exit_is_retried_and_fails() {
+        let path = temp_path("exit");
+        let _ = std::fs::remove_file(&path);
+        let command = format!("echo run >> {} && exit 3", path.display());
+        let args = exec_args("30s");
+        let retry =
+            crate::cli_args::RetryArgs::parse_from(["test", "--retry=1", "--retry-wait=1ms"]);
+        let exec = super::Exec::new(&args, &retry, &command);
+        let err = exec.deliver(&payload()).unwrap_err();
+        assert!(err.to_string().contains("exit status: 3"), "{err}");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "run\nrun\n");
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn command_is_killed_after_timeout() {
+        let args = exec_args("100ms");
+        let retry = crate::cli_args::RetryArgs::parse_from(["test", "--retry=0"]);
+        let exec = super::Exec::new(&args, &retry, "sleep 5");
+        let start = std::time::Instant::now();
+        let err = exec.deliver(&payload()).unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn update_is_written_to_stdin() {
+        let path = temp_path("stdin");
+        let command = format!(
+            "cat > {} && test \"$REDMINE_EVENT\" = updated",
+            path.display()
+        );
+        let args = exec_args("30s");
+        let retry = crate::cli_args::RetryArgs::parse_from(["test", "--retry=0"]);
+        let exec = super::Exec::new(&args, &retry, &command);
+        exec.deliver(&payload()).unwrap();
+        let stdin = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&stdin).unwrap(),
+            payload()
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+}
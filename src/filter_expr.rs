@@ -0,0 +1,635 @@
+// Copyright © ArkBig
+//! This file provides boolean filter expression over issue fields.
+//!
+//! e.g.) `tracker = Bug AND (priority in [High, Urgent] OR assigned_to = me) AND NOT subject ~ /WIP/`
+//!
+//! - Fields: `new_issue` keys (e.g. `tracker`, `status`, `assigned_to`, `custom_fields`, `project.name`),
+//!   `old.<key>` for the old values, `event`, `changed_fields`, `author` (update authors)
+//!   and `notes` (update contents).
+//!   If the field is an object such as `tracker`, it matches by name or id.
+//! - Operators: `=`, `!=`, `<`, `<=`, `>`, `>=`, `in [..]`, `~ /regex/`, `!~ /regex/`,
+//!   `changed(<key>)`, `AND` (`&&`), `OR` (`||`), `NOT` (`!`) and parentheses.
+//! - Values: bare words, quoted strings, numbers and `me` (the API key owner).
+
+/// Routing rule that limits updates to the destination
+///
+/// Format: `<DEST>=<EXPR>` (e.g. `slack=tracker = Bug`)
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub destination: String,
+    pub expr: Expr,
+}
+
+impl std::str::FromStr for Route {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (destination, expr) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <DEST>=<EXPR>, but [{s}]"))?;
+        Ok(Route {
+            destination: destination.trim().to_string(),
+            expr: expr.parse().map_err(|e: ParseError| e.to_string())?,
+        })
+    }
+}
+
+/// Keep only the updates matching any routing rule of the destination.
+///
+/// If there is no rule for the destination, all updates are kept.
+pub fn route(
+    routes: &[Route],
+    destination: &str,
+    updates: Vec<crate::redmine::UpdateInfo>,
+) -> Vec<crate::redmine::UpdateInfo> {
+    let routes = routes
+        .iter()
+        .filter(|r| r.destination == destination)
+        .collect::<Vec<_>>();
+    if routes.is_empty() {
+        return updates;
+    }
+    updates
+        .into_iter()
+        .filter(|u| routes.iter().any(|r| r.expr.eval(u)))
+        .collect()
+}
+
+/// Parsed filter expression
+#[derive(Clone, Debug)]
+pub struct Expr {
+    node: Node,
+    text: String,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Compare(Vec<String>, Op, Vec<Literal>),
+    Match(Vec<String>, regex::Regex),
+    Changed(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug)]
+struct Literal {
+    text: String,
+    /// Bare word (not quoted)
+    bare: bool,
+}
+
+/// Syntax error with position
+#[derive(thiserror::Error, Debug)]
+#[error("Syntax error at position {pos}: {message}\n  {text}\n  {marker}^")]
+pub struct ParseError {
+    /// 1-based character position
+    pos: usize,
+    message: String,
+    text: String,
+    marker: String,
+}
+
+impl std::str::FromStr for Expr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser {
+            text: s,
+            tokens,
+            index: 0,
+        };
+        let node = parser.parse_or()?;
+        if let Some(token) = parser.peek() {
+            return Err(parser.error_at(token.pos, "Unexpected token"));
+        }
+        Ok(Expr {
+            node,
+            text: s.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl Expr {
+    /// Whether `me` is referenced.
+    pub fn uses_me(&self) -> bool {
+        fn walk(node: &Node) -> bool {
+            match node {
+                Node::And(a, b) | Node::Or(a, b) => walk(a) || walk(b),
+                Node::Not(a) => walk(a),
+                Node::Compare(_, _, values) => values.iter().any(|v| v.bare && v.text == "me"),
+                Node::Match(..) | Node::Changed(_) => false,
+            }
+        }
+        walk(&self.node)
+    }
+
+    /// Replace `me` with the user id.
+    pub fn resolve_me(&mut self, user_id: u32) {
+        fn walk(node: &mut Node, user_id: u32) {
+            match node {
+                Node::And(a, b) | Node::Or(a, b) => {
+                    walk(a, user_id);
+                    walk(b, user_id);
+                }
+                Node::Not(a) => walk(a, user_id),
+                Node::Compare(_, _, values) => {
+                    for v in values.iter_mut().filter(|v| v.bare && v.text == "me") {
+                        v.text = user_id.to_string();
+                    }
+                }
+                Node::Match(..) | Node::Changed(_) => {}
+            }
+        }
+        walk(&mut self.node, user_id);
+    }
+
+    /// Evaluate against the update.
+    pub fn eval(&self, update: &crate::redmine::UpdateInfo) -> bool {
+        eval_node(&self.node, update)
+    }
+}
+
+fn eval_node(node: &Node, update: &crate::redmine::UpdateInfo) -> bool {
+    match node {
+        Node::And(a, b) => eval_node(a, update) && eval_node(b, update),
+        Node::Or(a, b) => eval_node(a, update) || eval_node(b, update),
+        Node::Not(a) => !eval_node(a, update),
+        Node::Compare(path, op, values) => {
+            let candidates = resolve_field(path, update);
+            let any = |f: &dyn Fn(&str, &str) -> bool| {
+                candidates
+                    .iter()
+                    .any(|c| values.iter().any(|v| f(c, &v.text)))
+            };
+            match op {
+                Op::Eq => any(&|c, v| c == v),
+                Op::Ne => !any(&|c, v| c == v),
+                Op::Lt => any(&|c, v| compare(c, v).is_lt()),
+                Op::Le => any(&|c, v| compare(c, v).is_le()),
+                Op::Gt => any(&|c, v| compare(c, v).is_gt()),
+                Op::Ge => any(&|c, v| compare(c, v).is_ge()),
+            }
+        }
+        Node::Match(path, re) => resolve_field(path, update).iter().any(|c| re.is_match(c)),
+        Node::Changed(key) => update.changed_fields.contains(key),
+    }
+}
+
+/// Compare as numbers if possible, otherwise as strings. (ISO dates can be compared as strings.)
+fn compare(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+/// Resolve the field to candidate strings.
+fn resolve_field(path: &[String], update: &crate::redmine::UpdateInfo) -> Vec<String> {
+    let (root, path) = match path[0].as_str() {
+        "event" if path.len() == 1 => return vec![update.event.as_str().to_string()],
+        "changed_fields" if path.len() == 1 => return update.changed_fields.clone(),
+        "author" | "authors" if path.len() == 1 => return update.authors.clone(),
+        "notes" | "content" if path.len() == 1 => {
+            return update
+                .update_contents
+                .iter()
+                .map(|c| c.content.clone())
+                .collect()
+        }
+        "old" | "old_items" => (&update.old_items, &path[1..]),
+        "new_issue" => (&update.new_issue, &path[1..]),
+        _ => (&update.new_issue, path),
+    };
+    let mut value = root;
+    for key in path {
+        value = match value {
+            serde_json::Value::Array(items) => {
+                // Custom fields can be referenced by name. (e.g. custom_fields.Severity)
+                match items.iter().find(|item| item["name"] == **key) {
+                    Some(item) => &item["value"],
+                    None => &serde_json::Value::Null,
+                }
+            }
+            _ => &value[key],
+        };
+    }
+    let mut candidates = Vec::new();
+    collect_candidates(value, &mut candidates);
+    candidates
+}
+
+fn collect_candidates(value: &serde_json::Value, candidates: &mut Vec<String>) {
+    match value {
+        serde_json::Value::Null => {}
+        serde_json::Value::String(s) => candidates.push(s.clone()),
+        serde_json::Value::Bool(_) | serde_json::Value::Number(_) => {
+            candidates.push(value.to_string())
+        }
+        serde_json::Value::Object(map) => {
+            for key in ["name", "id"] {
+                if let Some(v) = map.get(key) {
+                    collect_candidates(v, candidates);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_candidates(item, candidates);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    Str(String),
+    Regex(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    /// 0-based character position
+    pos: usize,
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, ParseError> {
+    let chars = text.chars().collect::<Vec<_>>();
+    let error = |pos: usize, message: &str| make_error(text, pos, message);
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let pos = i;
+        let kind = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            ',' => TokenKind::Comma,
+            '"' | '\'' | '/' => {
+                // Quoted string or regex
+                let mut s = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error(pos, "Unterminated literal")),
+                        Some('\\') if chars.get(i + 1) == Some(&c) => {
+                            s.push(c);
+                            i += 2;
+                        }
+                        Some(ch) if *ch == c => break,
+                        Some(ch) => {
+                            s.push(*ch);
+                            i += 1;
+                        }
+                    }
+                }
+                if c == '/' {
+                    TokenKind::Regex(s)
+                } else {
+                    TokenKind::Str(s)
+                }
+            }
+            '=' | '!' | '<' | '>' | '~' | '&' | '|' => {
+                let next = chars.get(i + 1).copied();
+                let (op, len) = match (c, next) {
+                    ('=', Some('=')) => ("=", 2),
+                    ('=', _) => ("=", 1),
+                    ('!', Some('=')) => ("!=", 2),
+                    ('!', Some('~')) => ("!~", 2),
+                    ('!', _) => ("NOT", 1),
+                    ('<', Some('=')) => ("<=", 2),
+                    ('<', _) => ("<", 1),
+                    ('>', Some('=')) => (">=", 2),
+                    ('>', _) => (">", 1),
+                    ('~', _) => ("~", 1),
+                    ('&', Some('&')) => ("AND", 2),
+                    ('|', Some('|')) => ("OR", 2),
+                    _ => return Err(error(pos, &format!("Unexpected character '{}'", c))),
+                };
+                i += len - 1;
+                TokenKind::Op(op)
+            }
+            c if is_word_char(c) => {
+                let mut s = String::new();
+                while let Some(ch) = chars.get(i).filter(|ch| is_word_char(**ch)) {
+                    s.push(*ch);
+                    i += 1;
+                }
+                i -= 1;
+                match s.to_ascii_uppercase().as_str() {
+                    "AND" => TokenKind::Op("AND"),
+                    "OR" => TokenKind::Op("OR"),
+                    "NOT" => TokenKind::Op("NOT"),
+                    "IN" => TokenKind::Op("IN"),
+                    _ => TokenKind::Ident(s),
+                }
+            }
+            c => return Err(error(pos, &format!("Unexpected character '{}'", c))),
+        };
+        tokens.push(Token { kind, pos });
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '@')
+}
+
+fn make_error(text: &str, pos: usize, message: &str) -> ParseError {
+    ParseError {
+        pos: pos + 1,
+        message: message.to_string(),
+        text: text.to_string(),
+        marker: " ".repeat(pos),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).cloned();
+        self.index += 1;
+        token
+    }
+
+    fn end_pos(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn error_at(&self, pos: usize, message: &str) -> ParseError {
+        make_error(self.text, pos, message)
+    }
+
+    fn expect(&mut self, kind: TokenKind, what: &str) -> Result<Token, ParseError> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(token),
+            Some(token) => Err(self.error_at(token.pos, &format!("Expected {}", what))),
+            None => Err(self.error_at(self.end_pos(), &format!("Expected {}", what))),
+        }
+    }
+
+    fn is_op(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token { kind: TokenKind::Op(o), .. }) if *o == op)
+    }
+
+    fn parse_or(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_and()?;
+        while self.is_op("OR") {
+            self.next();
+            node = Node::Or(Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node, ParseError> {
+        let mut node = self.parse_not()?;
+        while self.is_op("AND") {
+            self.next();
+            node = Node::And(Box::new(node), Box::new(self.parse_not()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_not(&mut self) -> Result<Node, ParseError> {
+        if self.is_op("NOT") {
+            self.next();
+            return Ok(Node::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, ParseError> {
+        let Some(token) = self.next() else {
+            return Err(self.error_at(self.end_pos(), "Expected condition"));
+        };
+        match token.kind {
+            TokenKind::LParen => {
+                let node = self.parse_or()?;
+                self.expect(TokenKind::RParen, "')'")?;
+                Ok(node)
+            }
+            TokenKind::Ident(name) if name == "changed" => {
+                self.expect(TokenKind::LParen, "'('")?;
+                let key = match self.next() {
+                    Some(Token {
+                        kind: TokenKind::Ident(key),
+                        ..
+                    }) => key,
+                    Some(token) => return Err(self.error_at(token.pos, "Expected field name")),
+                    None => return Err(self.error_at(self.end_pos(), "Expected field name")),
+                };
+                self.expect(TokenKind::RParen, "')'")?;
+                Ok(Node::Changed(key))
+            }
+            TokenKind::Ident(field) => self.parse_comparison(field, token.pos),
+            _ => Err(self.error_at(token.pos, "Expected field name or '('")),
+        }
+    }
+
+    fn parse_comparison(&mut self, field: String, field_pos: usize) -> Result<Node, ParseError> {
+        let path = field.split('.').map(|s| s.to_string()).collect::<Vec<_>>();
+        if path.iter().any(|p| p.is_empty()) {
+            return Err(self.error_at(field_pos, "Invalid field name"));
+        }
+        let Some(token) = self.next() else {
+            return Err(self.error_at(self.end_pos(), "Expected operator"));
+        };
+        let op = match token.kind {
+            TokenKind::Op("=") => Op::Eq,
+            TokenKind::Op("!=") => Op::Ne,
+            TokenKind::Op("<") => Op::Lt,
+            TokenKind::Op("<=") => Op::Le,
+            TokenKind::Op(">") => Op::Gt,
+            TokenKind::Op(">=") => Op::Ge,
+            TokenKind::Op("IN") => {
+                self.expect(TokenKind::LBracket, "'['")?;
+                let mut values = vec![self.parse_literal()?];
+                while matches!(
+                    self.peek(),
+                    Some(Token {
+                        kind: TokenKind::Comma,
+                        ..
+                    })
+                ) {
+                    self.next();
+                    values.push(self.parse_literal()?);
+                }
+                self.expect(TokenKind::RBracket, "']'")?;
+                return Ok(Node::Compare(path, Op::Eq, values));
+            }
+            TokenKind::Op(op @ ("~" | "!~")) => {
+                let re = match self.next() {
+                    Some(Token {
+                        kind: TokenKind::Regex(re) | TokenKind::Str(re),
+                        pos,
+                    }) => regex::Regex::new(&re)
+                        .map_err(|e| self.error_at(pos, &format!("Invalid regex with {}", e)))?,
+                    Some(token) => return Err(self.error_at(token.pos, "Expected /regex/")),
+                    None => return Err(self.error_at(self.end_pos(), "Expected /regex/")),
+                };
+                let node = Node::Match(path, re);
+                return Ok(if op == "~" {
+                    node
+                } else {
+                    Node::Not(Box::new(node))
+                });
+            }
+            _ => return Err(self.error_at(token.pos, "Expected operator")),
+        };
+        Ok(Node::Compare(path, op, vec![self.parse_literal()?]))
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, ParseError> {
+        match self.next() {
+            Some(Token {
+                kind: TokenKind::Ident(text),
+                ..
+            }) => Ok(Literal { text, bare: true }),
+            Some(Token {
+                kind: TokenKind::Str(text),
+                ..
+            }) => Ok(Literal { text, bare: false }),
+            Some(token) => Err(self.error_at(token.pos, "Expected value")),
+            None => Err(self.error_at(self.end_pos(), "Expected value")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Expr;
+
+    fn update() -> crate::redmine::UpdateInfo {
+        serde_json::from_value(serde_json::json!({
+            "url": "https://redmine.example.com/issues/1",
+            "event": "updated",
+            "local_updated_time": "2024-01-02T03:04:05+09:00",
+            "new_issue": {
+                "id": 1,
+                "subject": "WIP: Crash on save",
+                "tracker": { "id": 1, "name": "Bug" },
+                "priority": { "id": 4, "name": "Urgent" },
+                "assigned_to": { "id": 5, "name": "Alice" },
+                "done_ratio": 30,
+                "custom_fields": [{ "id": 2, "name": "Severity", "value": "Major" }],
+            },
+            "old_items": { "priority": { "id": 2, "name": "Normal" } },
+            "changed_fields": ["priority", "notes"],
+            "authors": ["Bob"],
+            "update_contents": [{ "author": "Bob", "content": "See the **log**." }],
+        }))
+        .unwrap()
+    }
+
+    fn eval(text: &str) -> bool {
+        text.parse::<Expr>().unwrap().eval(&update())
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert!(eval(
+            "tracker = Feature AND priority = Low OR tracker = Bug"
+        ));
+        assert!(!eval(
+            "tracker = Feature AND (priority = Low OR tracker = Bug)"
+        ));
+        assert!(eval("NOT tracker = Feature AND priority = Urgent"));
+        assert!(!eval(
+            "!(tracker = Bug || priority = Low) && done_ratio > 0"
+        ));
+    }
+
+    #[test]
+    fn compares_fields_and_values() {
+        assert!(eval("tracker = 1 AND priority in [High, Urgent]"));
+        assert!(eval("done_ratio >= 30 AND done_ratio < 100"));
+        assert!(eval(
+            "custom_fields.Severity = Major AND old.priority = Normal"
+        ));
+        assert!(eval(
+            "event = updated AND changed(priority) AND author = Bob"
+        ));
+        assert!(!eval("assigned_to != Alice"));
+    }
+
+    #[test]
+    fn quoted_values_keep_spaces_and_escapes() {
+        assert!(eval(r#"subject = "WIP: Crash on save""#));
+        assert!(eval("subject = 'WIP: Crash on save'"));
+        assert!("subject = 'it\\'s'".parse::<Expr>().is_ok());
+        assert!(!eval("subject = WIP"));
+    }
+
+    #[test]
+    fn matches_regex() {
+        assert!(eval("subject ~ /^WIP/ AND notes ~ /\\*\\*log\\*\\*/"));
+        assert!(eval("subject !~ /(?i)feature/"));
+        assert!(!eval("subject ~ '^Crash'"));
+    }
+
+    #[test]
+    fn resolves_me() {
+        let mut expr = "assigned_to = me".parse::<Expr>().unwrap();
+        assert!(expr.uses_me());
+        expr.resolve_me(5);
+        assert!(expr.eval(&update()));
+        assert!(!"assigned_to = 'me'".parse::<Expr>().unwrap().uses_me());
+    }
+
+    #[test]
+    fn reports_error_position() {
+        let error = |text: &str| text.parse::<Expr>().unwrap_err().to_string();
+        assert!(error("tracker = Bug AND")
+            .starts_with("Syntax error at position 18: Expected condition"));
+        assert!(error("tracker Bug").starts_with("Syntax error at position 9: Expected operator"));
+        assert!(error("subject = \"WIP")
+            .starts_with("Syntax error at position 11: Unterminated literal"));
+        assert!(error("(tracker = Bug").starts_with("Syntax error at position 15: Expected ')'"));
+        assert!(error("subject ~ /[/").starts_with("Syntax error at position 11: Invalid regex"));
+        assert!(
+            error("tracker = Bug )").starts_with("Syntax error at position 15: Unexpected token")
+        );
+        assert!(error("tracker = Bug $").contains("\n  tracker = Bug $\n                ^"));
+    }
+}
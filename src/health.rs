@@ -0,0 +1,167 @@
+// Copyright © ArkBig
+//! This file provides the health and readiness status of the process.
+
+static HEALTH: std::sync::Mutex<Health> = std::sync::Mutex::new(Health::new());
+
+#[derive(Clone)]
+struct Health {
+    last_success: Option<chrono::DateTime<chrono::Utc>>,
+    last_error: Option<(chrono::DateTime<chrono::Utc>, String)>,
+    /// The previous date stored in the previous data file.
+    prev_date: Option<chrono::DateTime<chrono::Utc>>,
+    /// Whether the last request to Redmine got a response.
+    redmine_reachable: Option<bool>,
+    /// HTTP status of the last response from Redmine.
+    redmine_status: Option<u16>,
+}
+
+impl Health {
+    const fn new() -> Self {
+        Health {
+            last_success: None,
+            last_error: None,
+            prev_date: None,
+            redmine_reachable: None,
+            redmine_status: None,
+        }
+    }
+}
+
+/// Conditions of the readiness.
+pub struct Readiness {
+    /// The last successful cycle must be within this age. (None is no limit)
+    pub max_age: Option<std::time::Duration>,
+    /// The previous data file must be writable.
+    pub prev_data_path: String,
+}
+
+fn with<R>(f: impl FnOnce(&mut Health) -> R) -> R {
+    let mut health = HEALTH.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut health)
+}
+
+/// Record the result of the cycle.
+pub fn record_cycle(error: Option<&str>) {
+    let now = chrono::Utc::now();
+    with(|h| match error {
+        Some(msg) => h.last_error = Some((now, msg.to_string())),
+        None => h.last_success = Some(now),
+    });
+}
+
+pub fn set_prev_date(date: chrono::DateTime<chrono::Utc>) {
+    with(|h| {
+        h.prev_date = (chrono::DateTime::<chrono::Utc>::MIN_UTC < date).then_some(date);
+    });
+}
+
+/// Record the HTTP status of the response from Redmine. (None is no response)
+pub fn set_redmine_response(status: Option<u16>) {
+    with(|h| {
+        h.redmine_reachable = Some(status.is_some());
+        h.redmine_status = status;
+    });
+}
+
+pub fn redmine_status() -> Option<u16> {
+    with(|h| h.redmine_status)
+}
+
+/// Body of `/healthz`. The process is alive if it can respond.
+pub fn healthz() -> serde_json::Value {
+    serde_json::json!({ "status": "ok" })
+}
+
+/// Status code and body of `/readyz`.
+pub fn readyz(readiness: &Readiness) -> (u16, serde_json::Value) {
+    // The state file is checked outside the lock.
+    with(|h| h.clone()).readyz(readiness)
+}
+
+impl Health {
+    fn readyz(&self, readiness: &Readiness) -> (u16, serde_json::Value) {
+        let state_file = is_writable(&readiness.prev_data_path);
+        let cycle = self.last_success.is_some_and(|t| {
+            readiness.max_age.map_or(true, |max_age| {
+                (chrono::Utc::now() - t).to_std().unwrap_or_default() <= max_age
+            })
+        });
+        let redmine = self.redmine_reachable == Some(true);
+        let ready = cycle && state_file && redmine;
+        let body = serde_json::json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": {
+                "cycle": cycle,
+                "state_file": state_file,
+                "redmine": redmine,
+            },
+            "redmine_status": self.redmine_status,
+            "last_success": self.last_success.map(|t| t.to_rfc3339()),
+            "last_error": self.last_error.as_ref().map(|(_, msg)| msg),
+            "last_error_at": self.last_error.as_ref().map(|(t, _)| t.to_rfc3339()),
+            "prev_date": self.prev_date.map(|t| t.to_rfc3339()),
+        });
+        (if ready { 200 } else { 503 }, body)
+    }
+}
+
+/// Check the file can be written without modifying it.
+fn is_writable(path: &str) -> bool {
+    let path = std::path::Path::new(path);
+    if path.exists() {
+        return std::fs::OpenOptions::new().append(true).open(path).is_ok();
+    }
+    // Not created yet, so check the directory instead.
+    let probe = path.with_file_name(format!(
+        ".{}.readyz",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let writable = std::fs::File::create(&probe).is_ok();
+    let _ = std::fs::remove_file(&probe);
+    writable
+}
+
+#[cfg(test)]
+mod tests {
+    /// Health succeeded the given time ago.
+    fn succeeded(ago: chrono::Duration) -> super::Health {
+        super::Health {
+            last_success: Some(chrono::Utc::now() - ago),
+            redmine_reachable: Some(true),
+            redmine_status: Some(200),
+            ..super::Health::new()
+        }
+    }
+
+    fn readiness(max_age: Option<&str>, prev_data_path: &std::path::Path) -> super::Readiness {
+        super::Readiness {
+            max_age: max_age.map(|d| humantime::parse_duration(d).unwrap()),
+            prev_data_path: prev_data_path.to_string_lossy().to_string(),
+        }
+    }
+
+    #[test]
+    fn not_ready_after_max_age() {
+        let path = std::env::temp_dir().join(format!("readyz-{}.json", std::process::id()));
+        let health = succeeded(chrono::Duration::minutes(10));
+        assert_eq!(health.readyz(&readiness(None, &path)).0, 200);
+        assert_eq!(health.readyz(&readiness(Some("1h"), &path)).0, 200);
+
+        let (status, body) = health.readyz(&readiness(Some("5min"), &path));
+        assert_eq!(status, 503);
+        assert_eq!(body["checks"]["cycle"], false);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn not_ready_if_state_file_is_not_writable() {
+        let path = std::env::temp_dir()
+            .join(format!("readyz-missing-{}", std::process::id()))
+            .join("redmine-data.json");
+        let health = succeeded(chrono::Duration::zero());
+        let (status, body) = health.readyz(&readiness(None, &path));
+        assert_eq!(status, 503);
+        assert_eq!(body["checks"]["state_file"], false);
+        assert_eq!(body["checks"]["cycle"], true);
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright © ArkBig
+//! This file provides JSON Lines output of updates for piping into other tools.
+//!
+//! Each line is the update with `message` rendered by the plain-text template.
+
+/// JSON Lines output to stdout (`-`) or a file
+pub struct JsonLines<'a> {
+    template_path: &'a Option<String>,
+    path: &'a str,
+}
+
+impl<'a> JsonLines<'a> {
+    pub fn new(template_path: &'a Option<String>, path: &'a str) -> Self {
+        JsonLines {
+            template_path,
+            path,
+        }
+    }
+}
+
+impl crate::notifier::Notifier for JsonLines<'_> {
+    fn name(&self) -> &str {
+        "jsonl"
+    }
+
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        let message = crate::template::Template::load(
+            self.template_path,
+            "jsonl-message",
+            include_str!("../resources/jsonl-message.template"),
+        )
+        .render(update)?;
+        let mut line = serde_json::to_value(update)?;
+        line["message"] = serde_json::Value::String(message);
+        Ok(line)
+    }
+
+    /// Append the payload as one line.
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        use std::io::Write as _;
+        let line = format!("{}\n", payload);
+        if self.path == "-" {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(line.as_bytes())?;
+            stdout.flush()?;
+        } else {
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.path)?;
+            file.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+}
@@ -7,6 +7,25 @@ This is main process of [redmine-to-slack](https://github.com/arkbig/redmine-to-
 
 pub mod app;
 mod cli_args;
+mod digest;
+mod discord;
+mod email;
+mod exec;
+mod filter_expr;
+mod health;
+mod jsonl;
 mod log;
+mod mattermost;
+mod metrics;
+mod mrkdwn;
+mod notifier;
+mod ops;
+mod privacy;
+mod redact;
 mod redmine;
+mod schedule;
+mod server;
 mod slack;
+mod teams;
+mod template;
+mod webhook;
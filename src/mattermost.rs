@@ -0,0 +1,136 @@
+// Copyright © ArkBig
+//! This file provides notification to Mattermost (or Rocket.Chat) incoming webhook.
+//!
+//! The payload is similar to Slack, but the text is Markdown, the issue is shown as an attachment,
+//! and the username/icon can be overridden.
+
+/// Compatible service of the incoming webhook
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum Flavor {
+    Mattermost,
+    #[clap(name = "rocketchat")]
+    RocketChat,
+}
+
+/// Mattermost (or Rocket.Chat) incoming webhook
+pub struct Mattermost<'a> {
+    args: &'a crate::cli_args::MattermostArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    notify_url: &'a str,
+}
+
+impl<'a> Mattermost<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::MattermostArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        notify_url: &'a str,
+    ) -> Self {
+        Mattermost {
+            args,
+            retry,
+            notify_url,
+        }
+    }
+}
+
+impl crate::notifier::Notifier for Mattermost<'_> {
+    fn name(&self) -> &str {
+        "mattermost"
+    }
+
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        let template = crate::template::Template::load(
+            &self.args.mattermost_template_path,
+            "mattermost-notification",
+            include_str!("../resources/mattermost-notification.template"),
+        );
+        let text = template.render(update)?;
+        Ok(convert_to_payload(self.args, text.trim(), update))
+    }
+
+    fn capabilities(&self) -> crate::notifier::Capabilities {
+        crate::notifier::Capabilities { digest: true }
+    }
+
+    /// Render the digest as one Markdown message. (used for the catch-up after the quiet hours)
+    fn render_digest(&self, digest: &crate::digest::Digest) -> anyhow::Result<serde_json::Value> {
+        let mut msg = serde_json::json!({ "text": crate::digest::to_markdown(digest).trim() });
+        override_sender(self.args, &mut msg);
+        Ok(msg)
+    }
+
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        crate::webhook::post_json(self.retry, self.notify_url, payload, "mattermost")?;
+        Ok(())
+    }
+}
+
+fn convert_to_payload(
+    args: &crate::cli_args::MattermostArgs,
+    text: &str,
+    update: &crate::redmine::UpdateInfo,
+) -> serde_json::Value {
+    let issue = &update.new_issue;
+    let title = format!(
+        "#{} {}",
+        issue["id"],
+        crate::notifier::name_of(&issue["subject"])
+    );
+    let fields = crate::notifier::field_changes(update)
+        .into_iter()
+        .map(|(name, value)| serde_json::json!({ "short": true, "title": name, "value": value }))
+        .collect::<Vec<_>>();
+    let attachment = serde_json::json!({
+        "fallback": format!("{} {}", title, update.url),
+        "color": color_of(update.event),
+        "title": title,
+        "title_link": update.url,
+        "fields": fields,
+    });
+
+    let mut msg = serde_json::json!({
+        "text": text,
+        "attachments": [attachment],
+    });
+    override_sender(args, &mut msg);
+    if args.mattermost_flavor == Flavor::Mattermost {
+        // Shown in the message info of Mattermost.
+        msg["props"] = serde_json::json!({
+            "card": format!("**[{}]({})**\n\n{}", title, update.url, crate::notifier::name_of(&issue["description"])),
+            "redmine_issue_id": issue["id"],
+            "redmine_event": update.event,
+        });
+    }
+    msg
+}
+
+/// Override the username, icon and channel by the flavor.
+fn override_sender(args: &crate::cli_args::MattermostArgs, msg: &mut serde_json::Value) {
+    let overrides = match args.mattermost_flavor {
+        Flavor::Mattermost => [
+            ("username", &args.mattermost_username),
+            ("icon_url", &args.mattermost_icon_url),
+            ("channel", &args.mattermost_channel),
+        ],
+        Flavor::RocketChat => [
+            ("alias", &args.mattermost_username),
+            ("avatar", &args.mattermost_icon_url),
+            ("channel", &args.mattermost_channel),
+        ],
+    };
+    for (key, value) in overrides {
+        if let Some(value) = value {
+            msg[key] = serde_json::json!(value);
+        }
+    }
+}
+
+/// Attachment color by the event kind.
+fn color_of(event: crate::redmine::EventKind) -> &'static str {
+    match event {
+        crate::redmine::EventKind::Created => "#2eb886",
+        crate::redmine::EventKind::Closed => "#9e9e9e",
+        crate::redmine::EventKind::Reopened => "#e67e22",
+        crate::redmine::EventKind::Updated | crate::redmine::EventKind::Commented => "#3498db",
+    }
+}
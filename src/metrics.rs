@@ -0,0 +1,268 @@
+// Copyright © ArkBig
+//! This file provides metrics in Prometheus text format.
+
+const PREFIX: &str = "redmine_to_slack";
+
+/// Upper bounds of the cycle duration histogram in seconds.
+const CYCLE_BUCKETS: [f64; 9] = [0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+static METRICS: std::sync::Mutex<Metrics> = std::sync::Mutex::new(Metrics::new());
+
+struct Metrics {
+    feed_fetches: u64,
+    /// By HTTP status (or `network`, `body`).
+    redmine_errors: std::collections::BTreeMap<String, u64>,
+    issues_detected: u64,
+    /// By sink and result (`sent` or `failed`).
+    notifications: std::collections::BTreeMap<(String, &'static str), u64>,
+    /// By sink.
+    retries: std::collections::BTreeMap<String, u64>,
+    cycle_buckets: [u64; CYCLE_BUCKETS.len()],
+    cycle_sum: f64,
+    cycle_count: u64,
+    tracked_issues: usize,
+    last_success: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Metrics {
+            feed_fetches: 0,
+            redmine_errors: std::collections::BTreeMap::new(),
+            issues_detected: 0,
+            notifications: std::collections::BTreeMap::new(),
+            retries: std::collections::BTreeMap::new(),
+            cycle_buckets: [0; CYCLE_BUCKETS.len()],
+            cycle_sum: 0.0,
+            cycle_count: 0,
+            tracked_issues: 0,
+            last_success: None,
+        }
+    }
+
+    fn observe_cycle(&mut self, duration: std::time::Duration, success: bool) {
+        let secs = duration.as_secs_f64();
+        // Buckets are cumulative. (counts of less than or equal to the bound)
+        for (bucket, le) in self.cycle_buckets.iter_mut().zip(CYCLE_BUCKETS) {
+            if secs <= le {
+                *bucket += 1;
+            }
+        }
+        self.cycle_sum += secs;
+        self.cycle_count += 1;
+        if success {
+            self.last_success = Some(chrono::Utc::now());
+        }
+    }
+
+    fn render(&self) -> String {
+        use std::fmt::Write as _;
+        let mut out = String::new();
+        header(
+            &mut out,
+            "feed_fetches_total",
+            "counter",
+            "Redmine Atom feed fetches.",
+        );
+        let _ = writeln!(out, "{PREFIX}_feed_fetches_total {}", self.feed_fetches);
+
+        header(
+            &mut out,
+            "redmine_errors_total",
+            "counter",
+            "Redmine request errors by HTTP status.",
+        );
+        for (status, count) in &self.redmine_errors {
+            let _ = writeln!(
+                out,
+                "{PREFIX}_redmine_errors_total{{status=\"{}\"}} {count}",
+                escape(status)
+            );
+        }
+
+        header(
+            &mut out,
+            "issues_detected_total",
+            "counter",
+            "Updated issues detected.",
+        );
+        let _ = writeln!(
+            out,
+            "{PREFIX}_issues_detected_total {}",
+            self.issues_detected
+        );
+
+        header(
+            &mut out,
+            "notifications_total",
+            "counter",
+            "Notifications by sink and result.",
+        );
+        for ((sink, result), count) in &self.notifications {
+            let _ = writeln!(
+                out,
+                "{PREFIX}_notifications_total{{sink=\"{}\",result=\"{result}\"}} {count}",
+                escape(sink)
+            );
+        }
+
+        header(
+            &mut out,
+            "retries_total",
+            "counter",
+            "Delivery retries by sink.",
+        );
+        for (sink, count) in &self.retries {
+            let _ = writeln!(
+                out,
+                "{PREFIX}_retries_total{{sink=\"{}\"}} {count}",
+                escape(sink)
+            );
+        }
+
+        header(
+            &mut out,
+            "cycle_duration_seconds",
+            "histogram",
+            "Duration of a cycle.",
+        );
+        for (le, count) in CYCLE_BUCKETS.iter().zip(self.cycle_buckets) {
+            let _ = writeln!(
+                out,
+                "{PREFIX}_cycle_duration_seconds_bucket{{le=\"{le}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{PREFIX}_cycle_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.cycle_count
+        );
+        let _ = writeln!(
+            out,
+            "{PREFIX}_cycle_duration_seconds_sum {}",
+            self.cycle_sum
+        );
+        let _ = writeln!(
+            out,
+            "{PREFIX}_cycle_duration_seconds_count {}",
+            self.cycle_count
+        );
+
+        header(
+            &mut out,
+            "tracked_issues",
+            "gauge",
+            "Issues stored in the previous data.",
+        );
+        let _ = writeln!(out, "{PREFIX}_tracked_issues {}", self.tracked_issues);
+
+        header(
+            &mut out,
+            "last_success_timestamp_seconds",
+            "gauge",
+            "Unix time of the last successful cycle.",
+        );
+        let _ = writeln!(
+            out,
+            "{PREFIX}_last_success_timestamp_seconds {}",
+            self.last_success.map_or(0, |t| t.timestamp())
+        );
+        out
+    }
+}
+
+fn with<R>(f: impl FnOnce(&mut Metrics) -> R) -> R {
+    let mut metrics = METRICS.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut metrics)
+}
+
+pub fn inc_feed_fetch() {
+    with(|m| m.feed_fetches += 1);
+}
+
+/// Count the error of Redmine by HTTP status. (or `network`, `body`)
+pub fn inc_redmine_error(status: &str) {
+    with(|m| *m.redmine_errors.entry(status.to_string()).or_default() += 1);
+}
+
+pub fn add_issues_detected(num: usize) {
+    with(|m| m.issues_detected += num as u64);
+}
+
+pub fn inc_notification(sink: &str, success: bool) {
+    let result = if success { "sent" } else { "failed" };
+    with(|m| {
+        *m.notifications
+            .entry((sink.to_string(), result))
+            .or_default() += 1
+    });
+}
+
+pub fn inc_retry(sink: &str) {
+    with(|m| *m.retries.entry(sink.to_string()).or_default() += 1);
+}
+
+/// Observe the duration of the cycle, and the time if succeeded.
+pub fn observe_cycle(duration: std::time::Duration, success: bool) {
+    with(|m| m.observe_cycle(duration, success));
+}
+
+pub fn set_tracked_issues(num: usize) {
+    with(|m| m.tracked_issues = num);
+}
+
+/// Render all metrics in Prometheus text format.
+pub fn render() -> String {
+    with(|m| m.render())
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    use std::fmt::Write as _;
+    let _ = writeln!(out, "# HELP {PREFIX}_{name} {help}");
+    let _ = writeln!(out, "# TYPE {PREFIX}_{name} {kind}");
+}
+
+/// Escape the label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut metrics = super::Metrics::new();
+        metrics.observe_cycle(std::time::Duration::from_millis(300), true);
+        metrics.observe_cycle(std::time::Duration::from_secs(3), false);
+        metrics.observe_cycle(std::time::Duration::from_secs(200), false);
+        let out = metrics.render();
+        for (le, count) in [
+            ("0.1", 0),
+            ("0.5", 1),
+            ("2.5", 1),
+            ("5", 2),
+            ("120", 2),
+            ("+Inf", 3),
+        ] {
+            let line =
+                format!("redmine_to_slack_cycle_duration_seconds_bucket{{le=\"{le}\"}} {count}\n");
+            assert!(out.contains(&line), "{line}");
+        }
+        assert!(out.contains("redmine_to_slack_cycle_duration_seconds_count 3\n"));
+        assert!(out.contains("redmine_to_slack_cycle_duration_seconds_sum 203.3\n"));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        let mut metrics = super::Metrics::new();
+        metrics
+            .notifications
+            .insert(("a\"b\\c\nd".to_string(), "sent"), 2);
+        assert!(metrics.render().contains(
+            "redmine_to_slack_notifications_total{sink=\"a\\\"b\\\\c\\nd\",result=\"sent\"} 2\n"
+        ));
+    }
+}
@@ -0,0 +1,462 @@
+// Copyright © ArkBig
+//! This file provides conversion from Redmine HTML to Markdown, and from it to Slack mrkdwn.
+//!
+//! Contents are kept as Markdown in the update info so that every notifier gets neutral text,
+//! and only Slack converts them to mrkdwn.
+
+/// Convert HTML to Markdown.
+///
+/// Characters are not escaped. If the text exceeds `max_length` characters, it is truncated with `...`,
+/// and the markup opened at that time is closed.
+pub fn markdown_from_html(html: &str, max_length: usize) -> String {
+    let tag_re =
+        regex::Regex::new(r"(?s)<(/?)([a-zA-Z][a-zA-Z0-9]*)([^>]*?)/?>|<!--.*?-->").unwrap();
+    let href_re =
+        regex::Regex::new(r#"(?i)\bhref\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#).unwrap();
+    let alt_re = regex::Regex::new(r#"(?i)\balt\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+
+    let mut w = Writer::new(max_length);
+    let mut pos = 0;
+    for cap in tag_re.captures_iter(html) {
+        let m = cap.get(0).unwrap();
+        w.text(&decode_entities(&html[pos..m.start()]));
+        pos = m.end();
+        if w.is_full() {
+            break;
+        }
+        let Some(name) = cap.get(2) else {
+            // Comment
+            continue;
+        };
+        let is_close = !cap[1].is_empty();
+        let attrs = &cap[3];
+        match (name.as_str().to_ascii_lowercase().as_str(), is_close) {
+            ("p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "table", false) => {
+                w.block();
+                if name.as_str().len() == 2 && name.as_str().starts_with(['h', 'H']) {
+                    w.open("**");
+                }
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", true) => {
+                w.close("**");
+                w.block();
+            }
+            ("p" | "div" | "table", true) => w.block(),
+            ("br", _) | ("tr", true) => w.newline(),
+            ("td" | "th", true) => w.text(" | "),
+            ("strong" | "b", false) => w.open("**"),
+            ("strong" | "b", true) => w.close("**"),
+            ("em" | "i", false) => w.open("_"),
+            ("em" | "i", true) => w.close("_"),
+            ("del" | "s" | "strike", false) => w.open("~~"),
+            ("del" | "s" | "strike", true) => w.close("~~"),
+            ("code", false) if !w.in_pre => w.open("`"),
+            ("code", true) if !w.in_pre => w.close("`"),
+            ("pre", false) => {
+                w.block();
+                w.open("```\n");
+                w.in_pre = true;
+            }
+            ("pre", true) => {
+                w.in_pre = false;
+                w.close("```\n");
+                w.block();
+            }
+            ("a", false) => {
+                let href = href_re.captures(attrs).and_then(|c| {
+                    c.get(1)
+                        .or_else(|| c.get(2))
+                        .or_else(|| c.get(3))
+                        .map(|m| decode_entities(m.as_str()))
+                });
+                w.open_link(href);
+            }
+            ("a", true) => w.close_link(),
+            ("img", _) => {
+                if let Some(alt) = alt_re
+                    .captures(attrs)
+                    .and_then(|c| c.get(1).or_else(|| c.get(2)))
+                {
+                    w.text(&decode_entities(alt.as_str()));
+                }
+            }
+            ("ul", false) => w.lists.push(None),
+            ("ol", false) => w.lists.push(Some(0)),
+            ("ul" | "ol", true) => {
+                w.lists.pop();
+                if w.lists.is_empty() {
+                    w.block();
+                }
+            }
+            ("li", false) => w.list_item(),
+            ("blockquote", false) => {
+                w.block();
+                w.quote_depth += 1;
+            }
+            ("blockquote", true) => {
+                w.block();
+                w.quote_depth = w.quote_depth.saturating_sub(1);
+            }
+            _ => {}
+        }
+    }
+    if !w.is_full() {
+        w.text(&decode_entities(&html[pos..]));
+    }
+    w.finish()
+}
+
+/// Convert Markdown made by [`markdown_from_html`] to Slack mrkdwn.
+///
+/// Links, bold, strikethrough and bullets are converted, and `&`, `<` and `>` are escaped.
+/// Code is only escaped.
+pub fn from_markdown(markdown: &str) -> String {
+    let inline_re = regex::Regex::new(
+        r"`[^`\n]*`|\[([^\[\]\n]*)\]\(([^()\s]+)\)|<([a-zA-Z][a-zA-Z0-9+.-]*:[^<>\s]+)>|\*\*([^*\n]+)\*\*|~~([^~\n]+)~~",
+    )
+    .unwrap();
+    let mut in_code = false;
+    markdown
+        .split('\n')
+        .map(|line| {
+            // Blockquote markers are kept as is.
+            let body = line.trim_start_matches("> ");
+            let quote = &line[..line.len() - body.len()];
+            let code_fence = body.trim_start().starts_with("```");
+            if in_code || code_fence {
+                in_code ^= code_fence;
+                return format!("{}{}", quote, crate::template::slack_escape(body));
+            }
+            let rest = body.trim_start();
+            let indent = &body[..body.len() - rest.len()];
+            match rest.strip_prefix("- ") {
+                Some(item) => format!("{}{}• {}", quote, indent, inline(&inline_re, item)),
+                None => format!("{}{}", quote, inline(&inline_re, body)),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn inline(inline_re: &regex::Regex, text: &str) -> String {
+    let escape = crate::template::slack_escape;
+    let mut out = String::new();
+    let mut pos = 0;
+    for cap in inline_re.captures_iter(text) {
+        let m = cap.get(0).unwrap();
+        out.push_str(&escape(&text[pos..m.start()]));
+        pos = m.end();
+        let converted = if let (Some(text), Some(href)) = (cap.get(1), cap.get(2)) {
+            format!(
+                "<{}|{}>",
+                escape(href.as_str()),
+                inline(inline_re, text.as_str())
+            )
+        } else if let Some(href) = cap.get(3) {
+            format!("<{}>", escape(href.as_str()))
+        } else if let Some(bold) = cap.get(4) {
+            format!("*{}*", inline(inline_re, bold.as_str()))
+        } else if let Some(strike) = cap.get(5) {
+            format!("~{}~", inline(inline_re, strike.as_str()))
+        } else {
+            escape(m.as_str())
+        };
+        out.push_str(&converted);
+    }
+    out.push_str(&escape(&text[pos..]));
+    out
+}
+
+/// Output writer that keeps track of the open markup.
+struct Writer {
+    out: String,
+    /// Remaining number of characters.
+    remaining: usize,
+    truncated: bool,
+    /// Opened markers to close at the truncation.
+    opened: Vec<&'static str>,
+    /// Link href and its text while in `<a>`.
+    link: Option<(Option<String>, String)>,
+    lists: Vec<Option<usize>>,
+    quote_depth: usize,
+    in_pre: bool,
+}
+
+impl Writer {
+    fn new(max_length: usize) -> Self {
+        Writer {
+            out: String::new(),
+            remaining: max_length,
+            truncated: false,
+            opened: Vec::new(),
+            link: None,
+            lists: Vec::new(),
+            quote_depth: 0,
+            in_pre: false,
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.truncated
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.truncated || text.is_empty() {
+            return;
+        }
+        // Whitespace is collapsed except in <pre>.
+        let text = if self.in_pre {
+            text.to_string()
+        } else {
+            let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let mut s = String::new();
+            if text.starts_with(char::is_whitespace) && !self.at_line_start() {
+                s.push(' ');
+            }
+            s.push_str(&collapsed);
+            if text.ends_with(char::is_whitespace) && !collapsed.is_empty() {
+                s.push(' ');
+            }
+            s
+        };
+        let count = text.chars().count();
+        let text = if self.remaining < count {
+            self.truncated = true;
+            text.chars().take(self.remaining).collect()
+        } else {
+            text
+        };
+        self.remaining -= text.chars().count().min(self.remaining);
+        for (i, line) in text.split('\n').enumerate() {
+            if 0 < i {
+                self.newline();
+            }
+            if !line.is_empty() {
+                self.write_raw(line);
+            }
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n') || self.out.ends_with("> ")
+    }
+
+    /// Write with blockquote prefix at the beginning of a line.
+    fn write_raw(&mut self, s: &str) {
+        if let Some((_, link_text)) = &mut self.link {
+            link_text.push_str(s);
+            return;
+        }
+        if 0 < self.quote_depth && (self.out.is_empty() || self.out.ends_with('\n')) {
+            self.out.push_str(&"> ".repeat(self.quote_depth));
+        }
+        self.out.push_str(s);
+    }
+
+    fn newline(&mut self) {
+        if self.truncated {
+            return;
+        }
+        if self.link.is_some() {
+            return;
+        }
+        self.out.push('\n');
+    }
+
+    /// Start a new block (paragraph) separated by a blank line.
+    fn block(&mut self) {
+        if self.truncated || self.out.is_empty() {
+            return;
+        }
+        if self.out.ends_with("\n\n") {
+            return;
+        }
+        let trimmed = self.out.trim_end_matches([' ', '\n']).len();
+        self.out.truncate(trimmed);
+        if self.quote_depth == 0 && self.lists.is_empty() {
+            self.out.push_str("\n\n");
+        } else {
+            self.out.push('\n');
+        }
+    }
+
+    fn open(&mut self, marker: &'static str) {
+        if self.truncated {
+            return;
+        }
+        self.write_raw(marker);
+        self.opened.push(marker);
+    }
+
+    fn close(&mut self, marker: &'static str) {
+        if self.truncated {
+            return;
+        }
+        if let Some(index) = self.opened.iter().rposition(|m| *m == marker) {
+            self.opened.remove(index);
+            let marker = if marker.starts_with("```") && !self.out.ends_with('\n') {
+                "\n```"
+            } else {
+                marker.trim_end_matches('\n')
+            };
+            self.write_raw(marker);
+        }
+    }
+
+    fn open_link(&mut self, href: Option<String>) {
+        if self.truncated {
+            return;
+        }
+        self.link = Some((href, String::new()));
+    }
+
+    fn close_link(&mut self) {
+        let Some((href, text)) = self.link.take() else {
+            return;
+        };
+        let link = match href {
+            Some(href) if text.is_empty() || text == href => format!("<{}>", href),
+            Some(href) => format!("[{}]({})", text, href),
+            None => text,
+        };
+        self.write_raw(&link);
+    }
+
+    fn list_item(&mut self) {
+        if self.truncated {
+            return;
+        }
+        if !self.at_line_start() {
+            self.out.push('\n');
+        }
+        let indent = "    ".repeat(self.lists.len().saturating_sub(1));
+        let bullet = match self.lists.last_mut() {
+            Some(Some(num)) => {
+                *num += 1;
+                format!("{}{}. ", indent, num)
+            }
+            _ => format!("{}- ", indent),
+        };
+        self.write_raw(&bullet);
+    }
+
+    fn finish(mut self) -> String {
+        if self.link.is_some() {
+            self.close_link();
+        }
+        if self.truncated {
+            // Close the markup in reverse order.
+            while let Some(marker) = self.opened.pop() {
+                if marker.starts_with("```") {
+                    self.out.push_str("\n```\n");
+                } else {
+                    self.out.push_str(marker);
+                }
+            }
+            self.out.push_str("...");
+        }
+        let out = self.out.trim();
+        // Collapse excessive blank lines.
+        let blank_re = regex::Regex::new(r"\n{3,}").unwrap();
+        blank_re.replace_all(out, "\n\n").to_string()
+    }
+}
+
+/// Decode HTML character references.
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let entity_re = regex::Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    entity_re
+        .replace_all(text, |c: &regex::Captures| {
+            let entity = &c[1];
+            let ch = if let Some(hex) = entity.strip_prefix("#x").or(entity.strip_prefix("#X")) {
+                u32::from_str_radix(hex, 16).ok().and_then(char::from_u32)
+            } else if let Some(dec) = entity.strip_prefix('#') {
+                dec.parse::<u32>().ok().and_then(char::from_u32)
+            } else {
+                match entity {
+                    "amp" => Some('&'),
+                    "lt" => Some('<'),
+                    "gt" => Some('>'),
+                    "quot" => Some('"'),
+                    "apos" => Some('\''),
+                    "nbsp" => Some(' '),
+                    _ => None,
+                }
+            };
+            ch.map_or_else(|| c[0].to_string(), |ch| ch.to_string())
+        })
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_markdown, markdown_from_html};
+
+    #[test]
+    fn keeps_text_unescaped_in_markdown() {
+        let markdown = markdown_from_html("<p>a &lt;b&gt; &amp; <strong>c</strong></p>", 100);
+        assert_eq!(markdown, "a <b> & **c**");
+        assert_eq!(from_markdown(&markdown), "a &lt;b&gt; &amp; *c*");
+    }
+
+    #[test]
+    fn converts_links() {
+        let markdown = markdown_from_html(
+            r#"see <a href="https://example.com/?a=1&amp;b=2">the page</a> or <a href="https://example.com">https://example.com</a>"#,
+            100,
+        );
+        assert_eq!(
+            markdown,
+            "see [the page](https://example.com/?a=1&b=2) or <https://example.com>"
+        );
+        assert_eq!(
+            from_markdown(&markdown),
+            "see <https://example.com/?a=1&amp;b=2|the page> or <https://example.com>"
+        );
+    }
+
+    #[test]
+    fn converts_lists() {
+        let markdown = markdown_from_html(
+            "<ul><li>one<ul><li>nested</li></ul></li><li><del>two</del></li></ul><ol><li>first</li><li>second</li></ol>",
+            100,
+        );
+        assert_eq!(
+            markdown,
+            "- one\n    - nested\n- ~~two~~\n\n1. first\n2. second"
+        );
+        assert_eq!(
+            from_markdown(&markdown),
+            "• one\n    • nested\n• ~two~\n\n1. first\n2. second"
+        );
+    }
+
+    #[test]
+    fn escapes_only_in_code() {
+        let markdown = markdown_from_html(
+            "<p><code>a **b** &lt;c&gt;</code></p><pre>- x &amp; [y](z)\n**w**</pre>",
+            100,
+        );
+        assert_eq!(markdown, "`a **b** <c>`\n\n```\n- x & [y](z)\n**w**\n```");
+        assert_eq!(
+            from_markdown(&markdown),
+            "`a **b** &lt;c&gt;`\n\n```\n- x &amp; [y](z)\n**w**\n```"
+        );
+    }
+
+    #[test]
+    fn keeps_quotes_and_closes_markup_at_truncation() {
+        let markdown =
+            markdown_from_html("<blockquote><p>quoted <b>text</b></p></blockquote>", 100);
+        assert_eq!(markdown, "> quoted **text**");
+        assert_eq!(from_markdown(&markdown), "> quoted *text*");
+
+        assert_eq!(
+            markdown_from_html("<p><b>bold text</b></p>", 4),
+            "**bold**..."
+        );
+    }
+}
@@ -0,0 +1,586 @@
+// Copyright © ArkBig
+//! This file provides the notifier interface to plug in delivery backends.
+//!
+//! The fetch/diff pipeline produces [`crate::redmine::UpdateInfo`], and [`dispatch`] passes it
+//! to a [`Notifier`] with the per destination options such as routing rules and quiet hours.
+
+/// Delivery backend such as Slack
+pub trait Notifier {
+    /// Destination name for the per destination options such as `--route`.
+    fn name(&self) -> &str;
+
+    /// Features supported by the backend.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    /// Digest setting. It is used only if [`Capabilities::digest`] is supported.
+    fn digest_mode(&self) -> DigestMode {
+        DigestMode::default()
+    }
+
+    /// Render the update into the payload to deliver.
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value>;
+
+    /// Render the digest of updates into the payload to deliver.
+    fn render_digest(&self, _digest: &crate::digest::Digest) -> anyhow::Result<serde_json::Value> {
+        anyhow::bail!("{} does not support digest.", self.name())
+    }
+
+    /// Deliver the rendered payload.
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()>;
+}
+
+/// Features supported by the notifier
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Capabilities {
+    /// Multiple updates can be sent as one digest message.
+    pub digest: bool,
+}
+
+/// Digest setting of the notifier
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DigestMode {
+    /// Batch updates per run, not only after the quiet hours.
+    pub enabled: bool,
+    /// Batch window.
+    pub interval: Option<std::time::Duration>,
+}
+
+/// Names of all notifiers. (Destinations of the delivery options)
+pub const NAMES: [&str; 8] = [
+    "slack",
+    "teams",
+    "discord",
+    "mattermost",
+    "webhook",
+    "email",
+    "exec",
+    "jsonl",
+];
+
+/// Notifiers whose URL is specified.
+pub fn from_args(cli_args: &crate::cli_args::CliArgs) -> Vec<Box<dyn Notifier + '_>> {
+    let retry = &cli_args.retry;
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(url) = &cli_args.slack.notify_url {
+        notifiers.push(Box::new(crate::slack::Slack::new(
+            &cli_args.slack,
+            retry,
+            url,
+        )));
+    }
+    if let Some(url) = &cli_args.teams.teams_url {
+        notifiers.push(Box::new(crate::teams::Teams::new(
+            &cli_args.teams,
+            retry,
+            url,
+        )));
+    }
+    if let Some(url) = &cli_args.discord.discord_url {
+        notifiers.push(Box::new(crate::discord::Discord::new(
+            &cli_args.discord,
+            retry,
+            url,
+        )));
+    }
+    if let Some(url) = &cli_args.mattermost.mattermost_url {
+        notifiers.push(Box::new(crate::mattermost::Mattermost::new(
+            &cli_args.mattermost,
+            retry,
+            url,
+        )));
+    }
+    if let Some(url) = &cli_args.webhook.webhook_url {
+        notifiers.push(Box::new(crate::webhook::Webhook::new(
+            &cli_args.webhook,
+            retry,
+            url,
+        )));
+    }
+    if let (Some(host), Some(from)) = (&cli_args.email.smtp_host, &cli_args.email.email_from) {
+        notifiers.push(Box::new(crate::email::Email::new(
+            &cli_args.email,
+            retry,
+            host,
+            from,
+        )));
+    }
+    if let Some(command) = &cli_args.exec.exec {
+        notifiers.push(Box::new(crate::exec::Exec::new(
+            &cli_args.exec,
+            retry,
+            command,
+        )));
+    }
+    if let Some(path) = &cli_args.jsonl {
+        notifiers.push(Box::new(crate::jsonl::JsonLines::new(
+            &cli_args.jsonl_template_path,
+            path,
+        )));
+    }
+    notifiers
+}
+
+/// Notify the updates to the notifier.
+///
+/// Routing rules, private data protection and quiet hours of the destination are applied.
+/// Updates queued in the quiet hours are sent as a catch-up digest afterwards
+/// (or one by one if the notifier does not support digest).
+/// Only the transient failures are queued for the next run, and the others are dropped.
+/// (see [`Failure`])
+pub fn dispatch(
+    args: &crate::cli_args::DeliveryArgs,
+    prev_redmine_data: &mut crate::redmine::RedmineData,
+    notifier: &dyn Notifier,
+    updated_issues: Vec<crate::redmine::UpdateInfo>,
+) -> anyhow::Result<()> {
+    let destination = notifier.name();
+    let updated_issues = crate::filter_expr::route(&args.route, destination, updated_issues);
+    let updated_issues = crate::privacy::protect(&args.allow_private, destination, updated_issues);
+    let pending = prev_redmine_data.pending_mut(destination);
+    if crate::schedule::is_quiet(&args.quiet_hours, destination) {
+        // Queue until the quiet hours end
+        let fields = serde_json::json!({ "sink": destination, "queue_num": updated_issues.len() });
+        crate::log::debug("Quiet hours.")
+            .category(destination)
+            .json(&fields);
+        pending.extend(updated_issues);
+        return Ok(());
+    }
+
+    let digest = notifier.digest_mode();
+    if notifier.capabilities().digest && (digest.enabled || !pending.is_empty()) {
+        // Notify the digest when the window has passed (or catch-up after the quiet hours)
+        let catch_up = !digest.enabled;
+        pending.extend(updated_issues);
+        if catch_up || pending.is_due(digest.interval) {
+            // Dequeued only after delivered, so that they are retried in the next run if failed.
+            if let Some(mut summary) = crate::digest::Digest::new(pending.updates().to_vec()) {
+                summary.catch_up = catch_up;
+                let result = render_and_deliver(notifier, notifier.render_digest(&summary));
+                match result {
+                    Ok(()) => {}
+                    Err(Failure::Transient(err)) => return Err(err),
+                    Err(Failure::Permanent(err)) => {
+                        let fields =
+                            serde_json::json!({ "sink": destination, "count": summary.count });
+                        log_dropped(destination, &err, fields);
+                        pending.take();
+                        return Err(err);
+                    }
+                }
+            }
+            pending.take();
+        }
+    } else {
+        // Notify each update (queued ones first)
+        pending.extend(updated_issues);
+        let mut dropped = None;
+        let mut updates = pending.take().into_iter();
+        while let Some(update) = updates.next() {
+            match render_and_deliver(notifier, notifier.render(&update)) {
+                Ok(()) => {}
+                Err(Failure::Transient(err)) => {
+                    // Queue the undelivered updates for the next run
+                    pending.extend(std::iter::once(update).chain(updates).collect());
+                    return Err(err);
+                }
+                Err(Failure::Permanent(err)) => {
+                    // Not to block the following updates
+                    let mut fields = crate::redmine::log_fields(&update.new_issue);
+                    fields["sink"] = serde_json::json!(destination);
+                    log_dropped(destination, &err, fields);
+                    dropped.get_or_insert(err);
+                }
+            }
+        }
+        if let Some(err) = dropped {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
+
+/// Failure of the notification
+enum Failure {
+    /// Network errors, 429 Too Many Requests and 5xx server errors, which may succeed later.
+    /// (Errors of the other delivery such as the command are also treated as transient.)
+    Transient(anyhow::Error),
+    /// Render errors, rejected requests (4xx) and permanent SMTP errors, which fail again.
+    Permanent(anyhow::Error),
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(err: anyhow::Error) -> Self {
+        let is_permanent = match err.downcast_ref::<ureq::Error>() {
+            Some(ureq::Error::Status(status, _)) => *status != 429 && *status < 500,
+            Some(ureq::Error::Transport(_)) => false,
+            None => err
+                .downcast_ref::<lettre::transport::smtp::Error>()
+                .is_some_and(|e| e.is_permanent()),
+        };
+        if is_permanent {
+            Failure::Permanent(err)
+        } else {
+            Failure::Transient(err)
+        }
+    }
+}
+
+fn render_and_deliver(
+    notifier: &dyn Notifier,
+    payload: anyhow::Result<serde_json::Value>,
+) -> Result<(), Failure> {
+    let payload = payload.map_err(|err| {
+        crate::metrics::inc_notification(notifier.name(), false);
+        Failure::Permanent(err)
+    })?;
+    Ok(deliver(notifier, &payload)?)
+}
+
+fn log_dropped(destination: &str, err: &anyhow::Error, fields: serde_json::Value) {
+    crate::log::error(&format!("Dropped the notification with {}", err))
+        .category(destination)
+        .json(&fields);
+}
+
+fn deliver(notifier: &dyn Notifier, payload: &serde_json::Value) -> anyhow::Result<()> {
+    crate::log::debug("Deliver")
+        .category(notifier.name())
+        .json(payload);
+    let result = notifier.deliver(payload);
+    crate::metrics::inc_notification(notifier.name(), result.is_ok());
+    result
+}
+
+/// Status, assignee and priority with the old values such as `~~New~~ → Open`.
+pub fn field_changes(update: &crate::redmine::UpdateInfo) -> Vec<(&'static str, String)> {
+    [
+        ("Status", "status"),
+        ("Assignee", "assigned_to"),
+        ("Priority", "priority"),
+    ]
+    .into_iter()
+    .map(|(name, key)| {
+        let new = or_dash(name_of(&update.new_issue[key]));
+        let value = match update.old_items.get(key) {
+            // Unchanged items are null in old_items.
+            Some(old) if !old.is_null() => format!("~~{}~~ → {}", or_dash(name_of(old)), new),
+            _ => new,
+        };
+        (name, value)
+    })
+    .collect()
+}
+
+/// Name of the object such as status, or the value itself.
+pub fn name_of(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Object(map) => map.get("name").map_or(String::new(), name_of),
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Null => String::new(),
+        v => v.to_string(),
+    }
+}
+
+fn or_dash(value: String) -> String {
+    if value.is_empty() {
+        "-".to_string()
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DigestMode, Notifier};
+    use clap::Parser;
+
+    fn update(id: u64, tracker: &str) -> crate::redmine::UpdateInfo {
+        serde_json::from_value(serde_json::json!({
+            "url": format!("https://redmine.example.com/issues/{}", id),
+            "event": "updated",
+            "local_updated_time": format!("2024-01-02T03:04:{:02}+09:00", id),
+            "new_issue": {
+                "id": id,
+                "subject": "Crash on save",
+                "tracker": { "id": 1, "name": tracker },
+                "project": { "id": 1, "name": "App" },
+                "status": { "id": 2, "name": "Open" },
+                "priority": { "id": 2, "name": "Normal" },
+            },
+            "old_items": {
+                "id": null,
+                "subject": null,
+                "tracker": null,
+                "project": null,
+                "status": { "id": 1, "name": "New" },
+                "priority": null,
+            },
+            "update_contents": [],
+        }))
+        .unwrap()
+    }
+
+    fn redmine_data() -> crate::redmine::RedmineData {
+        serde_json::from_value(serde_json::json!({
+            "prev_date": "2024-01-01T00:00:00Z",
+            "issues": {},
+        }))
+        .unwrap()
+    }
+
+    fn delivery_args(args: &[&str]) -> crate::cli_args::DeliveryArgs {
+        crate::cli_args::DeliveryArgs::parse_from(
+            std::iter::once("test").chain(args.iter().copied()),
+        )
+    }
+
+    /// Quiet hours around now.
+    fn quiet_now() -> String {
+        let now = chrono::Utc::now();
+        format!(
+            "slack={}-{}@UTC",
+            (now - chrono::Duration::hours(1)).format("%H:%M"),
+            (now + chrono::Duration::hours(1)).format("%H:%M")
+        )
+    }
+
+    /// Notifier that records the delivered payloads.
+    #[derive(Default)]
+    struct Recorder {
+        /// Digest is supported if specified.
+        digest: Option<DigestMode>,
+        /// Delivery fails after this number of deliveries.
+        fail_after: Option<usize>,
+        /// HTTP status of the failure. (Not HTTP error if not specified)
+        fail_status: Option<u16>,
+        /// Rendering of the issue fails.
+        fail_render: Option<u64>,
+        delivered: std::cell::RefCell<Vec<serde_json::Value>>,
+    }
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &str {
+            "slack"
+        }
+
+        fn capabilities(&self) -> super::Capabilities {
+            super::Capabilities {
+                digest: self.digest.is_some(),
+            }
+        }
+
+        fn digest_mode(&self) -> DigestMode {
+            self.digest.unwrap_or_default()
+        }
+
+        fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+            if self.fail_render == update.new_issue["id"].as_u64() {
+                anyhow::bail!("Render failed.");
+            }
+            Ok(update.new_issue["id"].clone())
+        }
+
+        fn render_digest(
+            &self,
+            digest: &crate::digest::Digest,
+        ) -> anyhow::Result<serde_json::Value> {
+            let ids = digest
+                .projects
+                .iter()
+                .flat_map(|p| p.updates.iter().map(|u| u.new_issue["id"].clone()))
+                .collect::<Vec<_>>();
+            Ok(serde_json::json!({ "ids": ids, "catch_up": digest.catch_up }))
+        }
+
+        fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+            let mut delivered = self.delivered.borrow_mut();
+            if self.fail_after == Some(delivered.len()) {
+                if let Some(status) = self.fail_status {
+                    let res = ureq::Response::new(status, "Error", "").unwrap();
+                    return Err(ureq::Error::Status(status, res).into());
+                }
+                anyhow::bail!("Delivery failed.");
+            }
+            delivered.push(payload.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn routes_only_matching_updates() {
+        let args = delivery_args(&[
+            "--route",
+            "slack=tracker = Bug",
+            "--route",
+            "teams=tracker = Feature",
+        ]);
+        let mut data = redmine_data();
+        let recorder = Recorder::default();
+        let updates = vec![update(1, "Bug"), update(2, "Feature"), update(3, "Bug")];
+        super::dispatch(&args, &mut data, &recorder, updates).unwrap();
+        assert_eq!(
+            *recorder.delivered.borrow(),
+            vec![serde_json::json!(1), serde_json::json!(3)]
+        );
+    }
+
+    #[test]
+    fn queues_in_quiet_hours_and_catches_up_as_digest() {
+        let mut data = redmine_data();
+        let recorder = Recorder {
+            digest: Some(DigestMode::default()),
+            ..Default::default()
+        };
+        let quiet = delivery_args(&["--quiet-hours", &quiet_now()]);
+        super::dispatch(&quiet, &mut data, &recorder, vec![update(1, "Bug")]).unwrap();
+        super::dispatch(&quiet, &mut data, &recorder, vec![update(2, "Bug")]).unwrap();
+        assert!(recorder.delivered.borrow().is_empty());
+        assert!(data.has_pending());
+
+        super::dispatch(
+            &delivery_args(&[]),
+            &mut data,
+            &recorder,
+            vec![update(3, "Bug")],
+        )
+        .unwrap();
+        assert_eq!(
+            *recorder.delivered.borrow(),
+            vec![serde_json::json!({ "ids": [1, 2, 3], "catch_up": true })]
+        );
+        assert!(!data.has_pending());
+    }
+
+    #[test]
+    fn delivers_digest_per_run() {
+        let mut data = redmine_data();
+        let recorder = Recorder {
+            digest: Some(DigestMode {
+                enabled: true,
+                interval: None,
+            }),
+            ..Default::default()
+        };
+        let updates = vec![update(1, "Bug"), update(2, "Bug")];
+        super::dispatch(&delivery_args(&[]), &mut data, &recorder, updates).unwrap();
+        assert_eq!(
+            *recorder.delivered.borrow(),
+            vec![serde_json::json!({ "ids": [1, 2], "catch_up": false })]
+        );
+        assert!(!data.has_pending());
+    }
+
+    #[test]
+    fn delivers_each_update_with_queued_ones_first() {
+        let mut data = redmine_data();
+        let recorder = Recorder::default();
+        let quiet = delivery_args(&["--quiet-hours", &quiet_now()]);
+        super::dispatch(&quiet, &mut data, &recorder, vec![update(1, "Bug")]).unwrap();
+        assert!(recorder.delivered.borrow().is_empty());
+
+        let updates = vec![update(2, "Bug"), update(3, "Bug")];
+        super::dispatch(&delivery_args(&[]), &mut data, &recorder, updates).unwrap();
+        assert_eq!(
+            *recorder.delivered.borrow(),
+            vec![
+                serde_json::json!(1),
+                serde_json::json!(2),
+                serde_json::json!(3)
+            ]
+        );
+        assert!(!data.has_pending());
+    }
+
+    #[test]
+    fn queues_undelivered_updates_on_failure() {
+        let args = delivery_args(&[]);
+        let mut data = redmine_data();
+        let recorder = Recorder {
+            fail_after: Some(1),
+            ..Default::default()
+        };
+        let updates = vec![update(1, "Bug"), update(2, "Bug"), update(3, "Bug")];
+        assert!(super::dispatch(&args, &mut data, &recorder, updates).is_err());
+        assert_eq!(*recorder.delivered.borrow(), vec![serde_json::json!(1)]);
+        let queued = data
+            .pending_mut("slack")
+            .updates()
+            .iter()
+            .map(|u| u.new_issue["id"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(queued, vec![serde_json::json!(2), serde_json::json!(3)]);
+
+        let recorder = Recorder::default();
+        super::dispatch(&args, &mut data, &recorder, vec![]).unwrap();
+        assert_eq!(
+            *recorder.delivered.borrow(),
+            vec![serde_json::json!(2), serde_json::json!(3)]
+        );
+        assert!(!data.has_pending());
+    }
+
+    #[test]
+    fn drops_update_failed_to_render() {
+        let args = delivery_args(&[]);
+        let mut data = redmine_data();
+        let recorder = Recorder {
+            fail_render: Some(1),
+            ..Default::default()
+        };
+        let updates = vec![update(1, "Bug"), update(2, "Bug"), update(3, "Bug")];
+        assert!(super::dispatch(&args, &mut data, &recorder, updates).is_err());
+        assert_eq!(
+            *recorder.delivered.borrow(),
+            vec![serde_json::json!(2), serde_json::json!(3)]
+        );
+        assert!(!data.has_pending());
+    }
+
+    #[test]
+    fn queues_only_transient_failures() {
+        let args = delivery_args(&[]);
+        for (status, queued) in [(400, false), (404, false), (429, true), (503, true)] {
+            let mut data = redmine_data();
+            let recorder = Recorder {
+                fail_after: Some(0),
+                fail_status: Some(status),
+                ..Default::default()
+            };
+            let updates = vec![update(1, "Bug")];
+            assert!(super::dispatch(&args, &mut data, &recorder, updates).is_err());
+            assert_eq!(data.has_pending(), queued, "status {status}");
+        }
+    }
+
+    #[test]
+    fn drops_digest_rejected_by_destination() {
+        let mut data = redmine_data();
+        let recorder = Recorder {
+            digest: Some(DigestMode {
+                enabled: true,
+                interval: None,
+            }),
+            fail_after: Some(0),
+            fail_status: Some(400),
+            ..Default::default()
+        };
+        let updates = vec![update(1, "Bug"), update(2, "Bug")];
+        assert!(super::dispatch(&delivery_args(&[]), &mut data, &recorder, updates).is_err());
+        assert!(!data.has_pending());
+    }
+
+    #[test]
+    fn field_changes_strike_only_changed_fields() {
+        assert_eq!(
+            super::field_changes(&update(1, "Bug")),
+            vec![
+                ("Status", "~~New~~ → Open".to_string()),
+                ("Assignee", "-".to_string()),
+                ("Priority", "Normal".to_string()),
+            ]
+        );
+    }
+}
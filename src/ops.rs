@@ -0,0 +1,85 @@
+// Copyright © ArkBig
+//! This file provides alerts to the ops channel when cycles keep failing.
+//!
+//! The number of consecutive failed cycles is stored in the previous data file,
+//! so it is counted across runs without `--interval`. (e.g. cron)
+//! In daemon mode, it is also kept in memory, so that the alert is posted
+//! even if the previous data file cannot be loaded or saved.
+
+/// Consecutive failed cycles in this process. (None before the first report)
+static STREAK: std::sync::Mutex<Option<u32>> = std::sync::Mutex::new(None);
+
+/// Count the result of the cycle, and post an alert or a recovery message if needed.
+pub fn report(cli_args: &crate::cli_args::CliArgs, error: Option<&proc_exit::Exit>) {
+    let Some(ops_url) = &cli_args.ops.ops_url else {
+        return;
+    };
+    if let Err(err) = update_streak(cli_args, ops_url, error) {
+        crate::log::error(&format!("Could not report to ops with {err}")).category("ops");
+    }
+}
+
+fn update_streak(
+    cli_args: &crate::cli_args::CliArgs,
+    ops_url: &str,
+    error: Option<&proc_exit::Exit>,
+) -> anyhow::Result<()> {
+    let prev_data = crate::redmine::load_prev_data(&cli_args.redmine);
+    let mut memory = STREAK.lock().unwrap_or_else(|e| e.into_inner());
+    // Read from the previous data file only at first.
+    let prev_streak = memory
+        .or_else(|| prev_data.as_ref().ok().map(|d| d.failure_streak()))
+        .unwrap_or_default();
+    let streak = if error.is_some() {
+        prev_streak.saturating_add(1)
+    } else {
+        0
+    };
+
+    let threshold = cli_args.ops.ops_failure_threshold;
+    let text = match error {
+        Some(err) if streak == threshold => Some(alert_text(cli_args, streak, err)),
+        None if threshold <= prev_streak => Some(recovery_text(cli_args, prev_streak)),
+        _ => None,
+    };
+    // Posted before counting, so that it is tried again in the next cycle if failed.
+    if let Some(text) = text {
+        let msg = serde_json::json!({ "text": crate::log::redact(&text) });
+        crate::log::debug("Post to ops.").category("ops").json(&msg);
+        crate::webhook::post_json(&cli_args.retry, ops_url, &msg, "ops")?;
+    }
+    *memory = Some(streak);
+    drop(memory);
+
+    let saved = prev_data.and_then(|mut prev_data| {
+        if prev_data.failure_streak() == streak {
+            return Ok(());
+        }
+        prev_data.set_failure_streak(streak);
+        crate::redmine::save_data(&cli_args.redmine, &prev_data)
+    });
+    if let Err(err) = saved {
+        let msg = format!("Could not save the failure streak with {err}");
+        crate::log::warning(&msg).category("ops");
+    }
+    Ok(())
+}
+
+fn alert_text(cli_args: &crate::cli_args::CliArgs, streak: u32, error: &proc_exit::Exit) -> String {
+    let mut text = format!(
+        ":rotating_light: *redmine-to-slack* failed {} times in a row. ({})",
+        streak, cli_args.redmine.subscribe_url
+    );
+    if let Some(status) = crate::health::redmine_status().filter(|s| 400 <= *s) {
+        text.push_str(&format!("\n*Redmine status:* {}", status));
+    }
+    text.push_str(&format!("\n```{}```", error));
+    text
+}
+
+fn recovery_text(cli_args: &crate::cli_args::CliArgs, prev_streak: u32) -> String {
+    format!(
+        ":white_check_mark: *redmine-to-slack* recovered after {} failed cycles. ({})",
+        prev_streak, cli_args.redmine.subscribe_url
+    )
+}
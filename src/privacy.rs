@@ -0,0 +1,46 @@
+// Copyright © ArkBig
+//! This file provides protection of private issues and private notes.
+
+/// Exclude private issues and private notes unless the destination allows them.
+///
+/// Skipped private notes are counted in `private_notes_skipped` for the template.
+/// If only private notes were updated, the update itself is excluded.
+pub fn protect(
+    allow_private: &[String],
+    destination: &str,
+    updates: Vec<crate::redmine::UpdateInfo>,
+) -> Vec<crate::redmine::UpdateInfo> {
+    if allow_private.iter().any(|d| d == destination) {
+        return updates;
+    }
+    updates
+        .into_iter()
+        .filter_map(|mut update| {
+            if update.new_issue["is_private"].as_bool().unwrap_or_default() {
+                let fields = crate::redmine::log_fields(&update.new_issue);
+                crate::log::debug("Skip private issue.")
+                    .category("privacy")
+                    .json(&fields);
+                return None;
+            }
+            let all_num = update.update_contents.len();
+            update.update_contents.retain(|c| !c.is_private);
+            let skipped = all_num - update.update_contents.len();
+            if skipped == 0 {
+                return Some(update);
+            }
+            update.private_notes_skipped += skipped;
+            let mut fields = crate::redmine::log_fields(&update.new_issue);
+            fields["skip_num"] = serde_json::json!(skipped);
+            crate::log::debug("Skip private notes.")
+                .category("privacy")
+                .json(&fields);
+            let is_comment_only = update.changed_fields.iter().all(|f| f == "notes");
+            if update.update_contents.is_empty() && is_comment_only {
+                None
+            } else {
+                Some(update)
+            }
+        })
+        .collect()
+}
@@ -2,19 +2,69 @@
 
 use std::collections::HashMap;
 
-#[derive(serde::Serialize, Clone)]
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct UpdateContent {
     pub author: String,
     pub content: String,
+    /// Journal id in Redmine. (None if it is not a journal such as a new issue)
+    #[serde(default)]
+    pub journal_id: Option<u64>,
+    /// Whether the note is private.
+    #[serde(default)]
+    pub is_private: bool,
 }
 
-#[derive(serde::Serialize, Clone)]
+/// Kind of the update event
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// New issue
+    Created,
+    /// Field update
+    Updated,
+    /// Comment only
+    Commented,
+    Closed,
+    Reopened,
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 5] = [
+        EventKind::Created,
+        EventKind::Updated,
+        EventKind::Commented,
+        EventKind::Closed,
+        EventKind::Reopened,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Updated => "updated",
+            EventKind::Commented => "commented",
+            EventKind::Closed => "closed",
+            EventKind::Reopened => "reopened",
+        }
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
 pub struct UpdateInfo {
     pub url: String,
+    pub event: EventKind,
     pub local_updated_time: chrono::DateTime<chrono::FixedOffset>,
     pub new_issue: serde_json::Value,
     pub old_items: serde_json::Value,
+    /// Keys changed from the previous snapshot. (`notes` if there are update contents)
+    #[serde(default)]
+    pub changed_fields: Vec<String>,
+    /// Authors of the updates in RSS.
+    #[serde(default)]
+    pub authors: Vec<String>,
     pub update_contents: Vec<UpdateContent>,
+    /// Number of private notes excluded from update_contents.
+    #[serde(default)]
+    pub private_notes_skipped: usize,
 }
 
 pub fn load_prev_data(args: &crate::cli_args::RedmineArgs) -> anyhow::Result<RedmineData> {
@@ -26,6 +76,8 @@ pub fn load_prev_data(args: &crate::cli_args::RedmineArgs) -> anyhow::Result<Red
             RedmineData {
                 prev_date: chrono::DateTime::<chrono::Utc>::MIN_UTC,
                 issues: HashMap::new(),
+                pending: HashMap::new(),
+                failure_streak: 0,
             }
         } else {
             crate::log::debug("load prev_data.").category("redmine");
@@ -37,14 +89,21 @@ pub fn load_prev_data(args: &crate::cli_args::RedmineArgs) -> anyhow::Result<Red
         RedmineData {
             prev_date: chrono::DateTime::<chrono::Utc>::MIN_UTC,
             issues: HashMap::new(),
+            pending: HashMap::new(),
+            failure_streak: 0,
         }
     };
     Ok(prev_data)
 }
 
+/// Get the updated issues since the previous run.
+///
+/// Journals are fetched to find the note authors and private notes only if `ignore_author` is
+/// specified or `mark_private` is true. (i.e. some destination excludes private notes)
 pub fn refresh_updated_issues(
     args: &crate::cli_args::RedmineArgs,
     prev_data: &mut RedmineData,
+    mark_private: bool,
 ) -> anyhow::Result<Vec<UpdateInfo>> {
     let url = &args.subscribe_url;
     let atom_key = &args.redmine_atom_key;
@@ -65,14 +124,88 @@ pub fn refresh_updated_issues(
     }
 
     // Get the issue information from the activity.
-    let updated_ids = activity_map.keys().map(|id| *id).collect::<Vec<_>>();
+    let updated_ids = activity_map.keys().copied().collect::<Vec<_>>();
     let new_issues = get_issues(url, api_key, &updated_ids)?;
 
     // Create a list of updates.
     let mut updates = Vec::new();
     for (id, new_issue) in &new_issues {
-        let old_issue = prev_data.issues.get(&id);
-        let old_items = get_updated_items(&old_issue, &new_issue)?;
+        if !is_target_issue(new_issue, filter) {
+            let fields = log_fields(new_issue);
+            crate::log::debug("Filtered out issue.")
+                .category("redmine")
+                .json(&fields);
+            continue;
+        }
+        let activities = &activity_map[id];
+        let needs_journals = filter.ignore_author.is_some() || mark_private;
+        let mut journals_failed = false;
+        let journals = if needs_journals && activities.iter().any(|a| a.journal_id.is_some()) {
+            get_journals(url, api_key, *id).unwrap_or_else(|e| {
+                // Notes of the issue are treated as private, not to fail the whole cycle.
+                let mut fields = log_fields(new_issue);
+                fields["error"] = serde_json::json!(e.to_string());
+                crate::log::warning("Could not get journals.")
+                    .category("redmine")
+                    .json(&fields);
+                journals_failed = true;
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        // Ignore changes by the authors (Compared by the user id of the journal or the issue.)
+        let activities = activities
+            .iter()
+            .filter(|a| {
+                filter.ignore_author.as_ref().map_or(true, |ignore_author| {
+                    let author = match a.journal_id {
+                        Some(journal_id) => journals.get(&journal_id).map(|j| &j["user"]),
+                        None => Some(&new_issue["author"]),
+                    };
+                    !author
+                        .and_then(|author| author["id"].as_u64())
+                        .is_some_and(|author_id| ignore_author.contains(&author_id.to_string()))
+                })
+            })
+            .collect::<Vec<_>>();
+        if activities.is_empty() {
+            let fields = log_fields(new_issue);
+            crate::log::debug("Filtered out issue by author.")
+                .category("redmine")
+                .json(&fields);
+            continue;
+        }
+        let mut authors = Vec::<String>::new();
+        for activity in &activities {
+            if !authors.contains(&activity.author) {
+                authors.push(activity.author.clone());
+            }
+        }
+        // Mark private notes
+        let update_contents = activities
+            .into_iter()
+            .filter(|a| !a.content.is_empty())
+            .map(|a| UpdateContent {
+                is_private: a.journal_id.is_some_and(|journal_id| {
+                    journals_failed
+                        || journals
+                            .get(&journal_id)
+                            .is_some_and(|j| j["private_notes"].as_bool().unwrap_or_default())
+                }),
+                ..a.clone()
+            })
+            .collect::<Vec<_>>();
+
+        let old_issue = prev_data.issues.get(id);
+        let old_items = get_updated_items(&old_issue, new_issue)?;
+        let mut changed_fields = old_issue.map_or_else(Vec::new, |old_issue| {
+            get_changed_fields(old_issue, new_issue)
+        });
+        if !update_contents.is_empty() {
+            changed_fields.push("notes".to_string());
+        }
+        let event = classify_event(&old_issue, new_issue, &update_contents, prev_data.prev_date);
         let local_updated_time = chrono::DateTime::parse_from_rfc3339(
             new_issue["updated_on"].as_str().unwrap_or_default(),
         )?;
@@ -82,17 +215,39 @@ pub fn refresh_updated_issues(
         } else {
             local_updated_time.with_timezone(chrono::Local::now().offset())
         };
-        updates.push(UpdateInfo {
+        let update = UpdateInfo {
             url: format!(
                 "{}/issues/{}",
                 url,
                 &new_issue["id"].as_u64().unwrap_or_default()
             ),
+            event,
             local_updated_time,
             new_issue: new_issue.clone(),
             old_items,
-            update_contents: activity_map[id].clone(),
-        });
+            changed_fields,
+            authors,
+            update_contents,
+            private_notes_skipped: 0,
+        };
+        if old_issue.is_some() && !is_notify_fields(&update.changed_fields, filter) {
+            let mut fields = log_fields(new_issue);
+            fields["fields"] = serde_json::json!(update.changed_fields);
+            crate::log::debug("Filtered out issue by changed fields.")
+                .category("redmine")
+                .json(&fields);
+            continue;
+        }
+        if let Some(expr) = &filter.filter_expr {
+            if !expr.eval(&update) {
+                let fields = log_fields(new_issue);
+                crate::log::debug("Filtered out issue by expression.")
+                    .category("redmine")
+                    .json(&fields);
+                continue;
+            }
+        }
+        updates.push(update);
     }
 
     // refresh issues
@@ -101,11 +256,18 @@ pub fn refresh_updated_issues(
     Ok(updates)
 }
 
+/// Structured log fields of the issue.
+pub fn log_fields(issue: &serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "issue_id": issue["id"],
+        "project": issue["project"]["name"],
+    })
+}
+
 pub fn save_purged_data(
     args: &crate::cli_args::RedmineArgs,
     prev_data: &mut RedmineData,
 ) -> anyhow::Result<()> {
-    let prev_data_path = &args.prev_redmine_data;
     let last_updated_date = prev_data
         .issues
         .values()
@@ -116,13 +278,11 @@ pub fn save_purged_data(
         })
         .max();
     if let Some(last_updated_date) = last_updated_date {
-        prev_data.prev_date = last_updated_date.into();
+        prev_data.prev_date = last_updated_date;
     }
-    // Remove old issues (and closed issues)
+    // Remove old issues (closed issues are kept to detect reopening)
     if chrono::DateTime::<chrono::Utc>::MIN_UTC < prev_data.prev_date {
-        let old_date = chrono::DateTime::<chrono::Utc>::from(
-            prev_data.prev_date - humantime::parse_duration("50day").unwrap(),
-        );
+        let old_date = prev_data.prev_date - humantime::parse_duration("50day").unwrap();
         let all_num = prev_data.issues.len();
         prev_data.issues = prev_data
             .issues
@@ -133,8 +293,7 @@ pub fn save_purged_data(
                 )
                 .unwrap_or_default()
                 .with_timezone(&chrono::Utc);
-                let is_closed = issue["status"]["is_closed"].as_bool().unwrap_or_default();
-                old_date < updated_on && !is_closed
+                old_date < updated_on
             })
             .map(|(id, issue)| (*id, issue.clone()))
             .collect();
@@ -148,7 +307,15 @@ pub fn save_purged_data(
         }
     }
 
-    // Save to file
+    save_data(args, prev_data)
+}
+
+/// Save to file as it is.
+pub fn save_data(
+    args: &crate::cli_args::RedmineArgs,
+    prev_data: &RedmineData,
+) -> anyhow::Result<()> {
+    let prev_data_path = &args.prev_redmine_data;
     crate::log::debug(&format!("save prev_data. path={}", prev_data_path)).category("redmine");
     let json = serde_json::to_string_pretty(prev_data)?;
     std::fs::write(prev_data_path, json)?;
@@ -215,6 +382,13 @@ pub fn get_users_map(url: &str, api_key: &Option<String>) -> anyhow::Result<Hash
     Ok(map)
 }
 
+pub fn get_current_user_id(url: &str, api_key: &Option<String>) -> anyhow::Result<u32> {
+    let json = get_json_from_api(&format!("{}/users/current.json", url), api_key)?;
+    let id = json["user"]["id"].as_u64().unwrap_or_default() as u32;
+    crate::log::debug(&format!("get_current_user_id. id={}", id)).category("redmine");
+    Ok(id)
+}
+
 fn get_page(url: &str, api_key: &Option<String>) -> anyhow::Result<String> {
     let request = ureq::get(url);
     let request = if let Some(api_key) = api_key {
@@ -223,16 +397,23 @@ fn get_page(url: &str, api_key: &Option<String>) -> anyhow::Result<String> {
         request
     };
     let response = request.call();
+    crate::health::set_redmine_response(match &response {
+        Ok(res) => Some(res.status()),
+        Err(ureq::Error::Status(code, _)) => Some(*code),
+        Err(_) => None,
+    });
     match response {
         Ok(res) => match res.into_string() {
             Ok(content) => Ok(content),
             Err(e) => {
+                crate::metrics::inc_redmine_error("body");
                 let msg = format!("Could not into string [{url}] with {e}");
                 crate::log::error(&msg).category("redmine");
                 Err(Error::Get(msg).into())
             }
         },
         Err(ureq::Error::Status(code, res)) => {
+            crate::metrics::inc_redmine_error(&code.to_string());
             let status = res.status_text().to_string();
             let msg = format!(
                 "Could not get page [{url}] Error: Status={code} {status}, Response={}",
@@ -242,6 +423,7 @@ fn get_page(url: &str, api_key: &Option<String>) -> anyhow::Result<String> {
             Err(Error::Get(msg).into())
         }
         Err(e) => {
+            crate::metrics::inc_redmine_error("network");
             let msg = format!("Could not get page [{url}] with {e}");
             crate::log::error(&msg).category("redmine");
             Err(Error::Get(msg).into())
@@ -265,6 +447,7 @@ fn get_atom_feed(url: &str, atom_key: &Option<String>) -> anyhow::Result<feed_rs
     } else {
         url.to_string()
     };
+    crate::metrics::inc_feed_fetch();
     let response = get_page(&url, &None)?;
     let feed = feed_rs::parser::parse(response.as_bytes())?;
     Ok(feed)
@@ -280,6 +463,42 @@ enum Error {
 pub struct RedmineData {
     prev_date: chrono::DateTime<chrono::Utc>,
     issues: HashMap<u64, serde_json::Value>,
+    /// Updates waiting for the digest delivery by destination.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pending: HashMap<String, crate::digest::Pending>,
+    /// Number of consecutive failed cycles.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    failure_streak: u32,
+}
+
+fn is_zero(num: &u32) -> bool {
+    *num == 0
+}
+
+impl RedmineData {
+    pub fn pending_mut(&mut self, destination: &str) -> &mut crate::digest::Pending {
+        self.pending.entry(destination.to_string()).or_default()
+    }
+
+    pub fn prev_date(&self) -> chrono::DateTime<chrono::Utc> {
+        self.prev_date
+    }
+
+    pub fn failure_streak(&self) -> u32 {
+        self.failure_streak
+    }
+
+    pub fn set_failure_streak(&mut self, streak: u32) {
+        self.failure_streak = streak;
+    }
+
+    pub fn tracked_issues(&self) -> usize {
+        self.issues.len()
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.pending.values().any(|p| !p.is_empty())
+    }
 }
 
 fn get_activities(
@@ -310,9 +529,8 @@ fn get_activities(
     }
     // Get activities
     let mut activity_map = HashMap::<u64, Vec<UpdateContent>>::new();
-    let prev_date = chrono::DateTime::<chrono::Utc>::from(prev_date);
     let id_re = regex::Regex::new(r".+/(\d+)").unwrap(); // get numbers from the last /
-    let html_re = regex::Regex::new(r"<[^>]*?>|\n").unwrap();
+    let journal_re = regex::Regex::new(r"#change-(\d+)").unwrap();
     for atom in activity_atoms {
         let feed = get_atom_feed(&atom, atom_key)?;
         let all_count = feed.entries.len();
@@ -343,34 +561,29 @@ fn get_activities(
             let id = id_re.captures(&entry.id).unwrap()[1]
                 .parse::<u64>()
                 .unwrap();
-            let content = entry.content.map_or(None, |c| c.body);
-
-            // Group by id
-            if let Some(content) = content {
-                let mut content = html_re.replace_all(&content, "").trim().to_string();
-                if !content.is_empty() {
-                    if max_content_length < content.chars().count() {
-                        content = format!(
-                            "{}...",
-                            &content.chars().take(max_content_length).collect::<String>()
-                        );
-                    }
-                    let update_content = UpdateContent {
-                        author: entry.authors[0].name.clone(),
-                        content,
-                    };
-                    if let Some(value) = activity_map.get_mut(&id) {
-                        // Since Atom is in descending order, insert it at the beginning to sort it in ascending order.
-                        value.insert(0, update_content);
-                    } else {
-                        activity_map.insert(id, vec![update_content]);
-                    }
-                } else if !activity_map.contains_key(&id) {
-                    activity_map.insert(id, vec![]);
-                }
-            } else if !activity_map.contains_key(&id) {
-                activity_map.insert(id, vec![]);
-            }
+            let content = entry.content.and_then(|c| c.body);
+
+            // Group by id (Entries without content are also kept for the author.)
+            let content = content
+                .map(|c| crate::mrkdwn::markdown_from_html(&c, max_content_length))
+                .unwrap_or_default();
+            let update_content = UpdateContent {
+                author: entry
+                    .authors
+                    .first()
+                    .map(|a| a.name.clone())
+                    .unwrap_or_default(),
+                content,
+                journal_id: journal_re
+                    .captures(&entry.id)
+                    .and_then(|c| c[1].parse::<u64>().ok()),
+                is_private: false,
+            };
+            // Since Atom is in descending order, insert it at the beginning to sort it in ascending order.
+            activity_map
+                .entry(id)
+                .or_default()
+                .insert(0, update_content);
             match_count += 1;
         } // end of 'entry
         let ignore_count = all_count - match_count;
@@ -417,6 +630,30 @@ fn get_issues(
     Ok(issues)
 }
 
+/// Journals of the issue by id. (Author and private flag of the notes)
+fn get_journals(
+    url: &str,
+    api_key: &Option<String>,
+    issue_id: u64,
+) -> anyhow::Result<HashMap<u64, serde_json::Value>> {
+    let issue_api = format!("{}/issues/{}.json?include=journals", url, issue_id);
+    let json = get_json_from_api(&issue_api, api_key)?;
+    let journals = json["issue"]["journals"]
+        .as_array()
+        .map(|journals| {
+            journals
+                .iter()
+                .filter_map(|j| Some((j["id"].as_u64()?, j.clone())))
+                .collect::<HashMap<_, _>>()
+        })
+        .unwrap_or_default();
+    let fields = serde_json::json!({ "issue_id": issue_id, "count": journals.len() });
+    crate::log::debug("get_journals.")
+        .category("redmine")
+        .json(&fields);
+    Ok(journals)
+}
+
 fn get_updated_items(
     old_issue: &Option<&serde_json::Value>,
     new_issue: &serde_json::Value,
@@ -444,3 +681,108 @@ fn get_updated_items(
     }
     Ok(serde_json::Value::Object(updated_items))
 }
+
+fn get_changed_fields(old_issue: &serde_json::Value, new_issue: &serde_json::Value) -> Vec<String> {
+    // Extract keys whose value is different, excluding the update time itself.
+    let mut keys = new_issue
+        .as_object()
+        .into_iter()
+        .chain(old_issue.as_object())
+        .flat_map(|map| map.keys())
+        .filter(|key| *key != "updated_on")
+        .filter(|key| old_issue.get(key) != new_issue.get(key))
+        .cloned()
+        .collect::<Vec<_>>();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+fn classify_event(
+    old_issue: &Option<&serde_json::Value>,
+    new_issue: &serde_json::Value,
+    update_contents: &[UpdateContent],
+    prev_date: chrono::DateTime<chrono::Utc>,
+) -> EventKind {
+    let parse_date = |key: &str| {
+        new_issue[key]
+            .as_str()
+            .and_then(|d| chrono::DateTime::parse_from_rfc3339(d).ok())
+            .map(|d| d.with_timezone(&chrono::Utc))
+    };
+    let is_closed = new_issue["status"]["is_closed"]
+        .as_bool()
+        .unwrap_or_default();
+    let Some(old_issue) = old_issue else {
+        // Without the previous snapshot, judge from the dates of the issue.
+        let created_on = parse_date("created_on");
+        let is_created = if chrono::DateTime::<chrono::Utc>::MIN_UTC < prev_date {
+            created_on.is_some_and(|d| prev_date < d)
+        } else {
+            created_on.is_some() && created_on == parse_date("updated_on")
+        };
+        if is_created {
+            return EventKind::Created;
+        }
+        if is_closed && parse_date("closed_on").is_some_and(|d| prev_date < d) {
+            return EventKind::Closed;
+        }
+        return EventKind::Updated;
+    };
+    let was_closed = old_issue["status"]["is_closed"]
+        .as_bool()
+        .unwrap_or_default();
+    if is_closed && !was_closed {
+        EventKind::Closed
+    } else if !is_closed && was_closed {
+        EventKind::Reopened
+    } else if get_changed_fields(old_issue, new_issue).is_empty() && !update_contents.is_empty() {
+        EventKind::Commented
+    } else {
+        EventKind::Updated
+    }
+}
+
+/// Whether the issue matches the post-fetch filters. (tracker, status, etc.)
+fn is_target_issue(issue: &serde_json::Value, filter: &crate::cli_args::FilterArgs) -> bool {
+    [
+        ("tracker", &filter.tracker, &filter.ignore_tracker),
+        ("status", &filter.status, &filter.ignore_status),
+        ("priority", &filter.priority, &filter.ignore_priority),
+        ("category", &filter.category, &filter.ignore_category),
+        (
+            "fixed_version",
+            &filter.fixed_version,
+            &filter.ignore_fixed_version,
+        ),
+        ("assigned_to", &filter.assignee, &filter.ignore_assignee),
+    ]
+    .iter()
+    .all(|(key, target, ignore)| {
+        let value = &issue[key];
+        let matches = |list: &Vec<String>| {
+            list.iter().any(|item| {
+                value["id"]
+                    .as_u64()
+                    .is_some_and(|id| id.to_string() == *item)
+                    || value["name"].as_str().is_some_and(|name| name == item)
+            })
+        };
+        target.as_ref().map_or(true, matches) && !ignore.as_ref().is_some_and(matches)
+    })
+}
+
+/// Whether the changed fields contain the fields to notify.
+fn is_notify_fields(changed_fields: &[String], filter: &crate::cli_args::FilterArgs) -> bool {
+    if let Some(notify_on_fields) = &filter.notify_on_fields {
+        if !changed_fields.iter().any(|f| notify_on_fields.contains(f)) {
+            return false;
+        }
+    }
+    if let Some(ignore_fields) = &filter.ignore_fields {
+        if changed_fields.iter().all(|f| ignore_fields.contains(f)) {
+            return false;
+        }
+    }
+    true
+}
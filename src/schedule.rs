@@ -0,0 +1,72 @@
+// Copyright © ArkBig
+//! This file provides delivery schedule such as quiet hours.
+
+/// Quiet hours of the destination
+///
+/// Format: `<DEST>=<HH:MM>-<HH:MM>[@<TIMEZONE>]` (e.g. `slack=22:00-07:00@Asia/Tokyo`)
+#[derive(Clone, Debug)]
+pub struct QuietHours {
+    pub destination: String,
+    start: chrono::NaiveTime,
+    end: chrono::NaiveTime,
+    /// If not specified, the os local time will be used.
+    timezone: Option<chrono_tz::Tz>,
+}
+
+impl std::str::FromStr for QuietHours {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (destination, range) = s
+            .split_once('=')
+            .ok_or_else(|| format!("Expected <DEST>=<HH:MM>-<HH:MM>[@<TIMEZONE>], but [{s}]"))?;
+        let (range, timezone) = match range.split_once('@') {
+            Some((range, tz)) => (
+                range,
+                Some(
+                    tz.parse::<chrono_tz::Tz>()
+                        .map_err(|e| format!("Invalid timezone [{tz}] with {e}"))?,
+                ),
+            ),
+            None => (range, None),
+        };
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("Expected <HH:MM>-<HH:MM>, but [{range}]"))?;
+        let parse_time = |t: &str| {
+            chrono::NaiveTime::parse_from_str(t.trim(), "%H:%M")
+                .map_err(|e| format!("Invalid time [{t}] with {e}"))
+        };
+        Ok(QuietHours {
+            destination: destination.trim().to_string(),
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            timezone,
+        })
+    }
+}
+
+impl QuietHours {
+    /// Whether the time is in the quiet hours.
+    ///
+    /// If the end is earlier than the start, it is over midnight. (e.g. 22:00-07:00)
+    pub fn contains(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let time = match self.timezone {
+            Some(tz) => now.with_timezone(&tz).time(),
+            None => now.with_timezone(&chrono::Local).time(),
+        };
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+/// Whether the destination is in the quiet hours now.
+pub fn is_quiet(quiet_hours: &[QuietHours], destination: &str) -> bool {
+    let now = chrono::Utc::now();
+    quiet_hours
+        .iter()
+        .any(|q| q.destination == destination && q.contains(now))
+}
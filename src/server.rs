@@ -0,0 +1,57 @@
+// Copyright © ArkBig
+//! This file provides the HTTP server for monitoring.
+//!
+//! - `/metrics`: Metrics in Prometheus text format.
+//! - `/healthz`: The process is alive.
+//! - `/readyz`: The last cycle succeeded recently, the state file is writable and Redmine is reachable.
+
+/// Start the server in background.
+pub fn start(addr: &str, readiness: crate::health::Readiness) -> anyhow::Result<()> {
+    let server = tiny_http::Server::http(addr)
+        .map_err(|e| anyhow::anyhow!("Could not listen [{addr}] with {e}"))?;
+    crate::log::debug(&format!("Listening. addr={}", addr)).category("server");
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle(request, &readiness);
+        }
+    });
+    Ok(())
+}
+
+fn handle(request: tiny_http::Request, readiness: &crate::health::Readiness) {
+    let path = request.url().split('?').next().unwrap_or_default();
+    let response = match (request.method(), path) {
+        (tiny_http::Method::Get, "/metrics") => text_response(
+            200,
+            "text/plain; version=0.0.4; charset=utf-8",
+            crate::metrics::render(),
+        ),
+        (tiny_http::Method::Get, "/healthz") => json_response(200, &crate::health::healthz()),
+        (tiny_http::Method::Get, "/readyz") => {
+            let (status, body) = crate::health::readyz(readiness);
+            json_response(status, &body)
+        }
+        _ => text_response(404, "text/plain; charset=utf-8", "Not Found\n".to_string()),
+    };
+    if let Err(e) = request.respond(response) {
+        crate::log::warning(&format!("Could not respond with {e}")).category("server");
+    }
+}
+
+fn text_response(
+    status: u16,
+    content_type: &str,
+    body: String,
+) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    let header = tiny_http::Header::from_bytes("Content-Type", content_type).unwrap();
+    tiny_http::Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header)
+}
+
+fn json_response(
+    status: u16,
+    body: &serde_json::Value,
+) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    text_response(status, "application/json", body.to_string())
+}
@@ -1,72 +1,133 @@
 // Copyright © ArkBig
 
-pub fn notify(
-    args: &crate::cli_args::SlackArgs,
-    notify_url: &str,
-    update: &crate::redmine::UpdateInfo,
-) -> anyhow::Result<()> {
-    let template_path = &args.template_path;
+/// Slack incoming webhook
+pub struct Slack<'a> {
+    args: &'a crate::cli_args::SlackArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    notify_url: &'a str,
+}
+
+impl<'a> Slack<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::SlackArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        notify_url: &'a str,
+    ) -> Self {
+        Slack {
+            args,
+            retry,
+            notify_url,
+        }
+    }
+}
 
-    let msg = convert_to_post_msg(template_path, update)?;
-    crate::log::debug(&msg).category("slack");
+impl crate::notifier::Notifier for Slack<'_> {
+    fn name(&self) -> &str {
+        "slack"
+    }
+
+    fn capabilities(&self) -> crate::notifier::Capabilities {
+        crate::notifier::Capabilities { digest: true }
+    }
 
-    ureq::post(notify_url)
-        .set("Content-Type", "application/json")
-        .send_json(ureq::json!({ "text": msg }))?;
+    fn digest_mode(&self) -> crate::notifier::DigestMode {
+        crate::notifier::DigestMode {
+            enabled: self.args.digest,
+            interval: self.args.digest_interval,
+        }
+    }
 
-    Ok(())
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        let msg = convert_to_post_msg(&self.args.template_dir, &self.args.template_path, update)?;
+        Ok(serde_json::json!({ "text": msg }))
+    }
+
+    /// Render the digest of updates as one message.
+    fn render_digest(&self, digest: &crate::digest::Digest) -> anyhow::Result<serde_json::Value> {
+        let template = crate::template::Template::load(
+            &self.args.digest_template_path,
+            "slack-digest",
+            include_str!("../resources/slack-digest.template"),
+        );
+        let digest = crate::digest::Digest {
+            projects: digest
+                .projects
+                .iter()
+                .map(|project| crate::digest::DigestProject {
+                    name: project.name.clone(),
+                    updates: project.updates.iter().map(to_mrkdwn).collect(),
+                })
+                .collect(),
+            ..*digest
+        };
+        let msg = template.render(&digest)?;
+        Ok(serde_json::json!({ "text": msg }))
+    }
+
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        crate::webhook::post_json(self.retry, self.notify_url, payload, "slack")?;
+        Ok(())
+    }
 }
 
-fn convert_to_post_msg(
+pub fn convert_to_post_msg(
+    template_dir: &Option<String>,
     template_path: &Option<String>,
     update: &crate::redmine::UpdateInfo,
 ) -> anyhow::Result<String> {
-    // Load template
-    let template = if let Some(path) = template_path {
-        std::fs::read_to_string(path).unwrap_or_else(|e| {
-            let msg = format!(
-                "Could not read template from file [{path}] with {e}",
-                path = path,
-                e = e
-            );
-            crate::log::error(&msg).category("slack");
-            std::process::exit(1);
-        })
-    } else {
-        include_str!("../resources/slack-notification.template").to_string()
-    };
+    let template = crate::template::Template::load_for_event(
+        template_dir,
+        update.event,
+        template_path,
+        "slack-notification",
+        include_str!("../resources/slack-notification.template"),
+    );
+    template.render(&to_mrkdwn(update))
+}
 
-    // Add referenced key of new_issue
+/// Convert the contents from Markdown to Slack mrkdwn.
+fn to_mrkdwn(update: &crate::redmine::UpdateInfo) -> crate::redmine::UpdateInfo {
     let mut update = update.clone();
-    let new_referenced_re = regex::Regex::new(r"\Wnew_issue.(?<key>[a-zA-Z_-]+)").unwrap();
-    for key in new_referenced_re
-        .captures_iter(&template)
-        .map(|c| c["key"].to_string())
-    {
-        if let Some(map) = update.new_issue.as_object_mut() {
-            if !map.contains_key(&key) {
-                map.insert(key, serde_json::Value::Null);
-            }
-        }
+    for content in &mut update.update_contents {
+        content.content = crate::mrkdwn::from_markdown(&content.content);
     }
-    // Add referenced key of old_items
-    let old_referenced_re = regex::Regex::new(r"\Wold_items.(?<key>[a-zA-Z_-]+)").unwrap();
-    for key in old_referenced_re
-        .captures_iter(&template)
-        .map(|c| c["key"].to_string())
-    {
-        if let Some(map) = update.old_items.as_object_mut() {
-            if !map.contains_key(&key) {
-                map.insert(key, serde_json::Value::Null);
-            }
-        }
-    }
-
-    // Render template
-    let mut tt = tinytemplate::TinyTemplate::new();
-    tt.set_default_formatter(&tinytemplate::format_unescaped);
-    tt.add_template("slack-notification", &template)?;
-    let msg = tt.render("slack-notification", &update)?;
+    update
+}
 
-    Ok(msg)
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn quotes_every_line_of_notes() {
+        let update: crate::redmine::UpdateInfo = serde_json::from_value(serde_json::json!({
+            "url": "https://redmine.example.com/issues/1",
+            "event": "commented",
+            "local_updated_time": "2024-01-02T03:04:05+09:00",
+            "new_issue": { "id": 1, "subject": "Crash on save" },
+            "old_items": {},
+            "update_contents": [{
+                "author": "Alice",
+                "content": "First **paragraph**.\n\nSecond paragraph:\n- one & two\n\n```\nlog\n```",
+            }],
+        }))
+        .unwrap();
+        let msg = super::convert_to_post_msg(&None, &None, &update).unwrap();
+        let note = msg
+            .lines()
+            .skip_while(|line| !line.starts_with("> Alice"))
+            .take_while(|line| line.starts_with('>'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            note,
+            vec![
+                "> Alice > First *paragraph*.",
+                "> ",
+                "> Second paragraph:",
+                "> • one &amp; two",
+                "> ",
+                "> ```",
+                "> log",
+                "> ```",
+            ]
+        );
+    }
 }
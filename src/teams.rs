@@ -0,0 +1,105 @@
+// Copyright © ArkBig
+//! This file provides notification to Microsoft Teams with Adaptive Cards.
+
+/// Teams incoming webhook
+pub struct Teams<'a> {
+    args: &'a crate::cli_args::TeamsArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    notify_url: &'a str,
+}
+
+impl<'a> Teams<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::TeamsArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        notify_url: &'a str,
+    ) -> Self {
+        Teams {
+            args,
+            retry,
+            notify_url,
+        }
+    }
+}
+
+impl crate::notifier::Notifier for Teams<'_> {
+    fn name(&self) -> &str {
+        "teams"
+    }
+
+    /// Render the update as an Adaptive Card message.
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        let card = convert_to_card(&self.args.teams_template_path, update)?;
+        Ok(wrap_card(card))
+    }
+
+    fn capabilities(&self) -> crate::notifier::Capabilities {
+        crate::notifier::Capabilities { digest: true }
+    }
+
+    /// Render the digest as one Adaptive Card. (used for the catch-up after the quiet hours)
+    fn render_digest(&self, digest: &crate::digest::Digest) -> anyhow::Result<serde_json::Value> {
+        let mut body = vec![serde_json::json!({
+            "type": "TextBlock",
+            "text": digest.heading(),
+            "weight": "Bolder",
+            "size": "Medium",
+            "wrap": true,
+        })];
+        for project in &digest.projects {
+            let lines = project
+                .updates
+                .iter()
+                .map(crate::digest::markdown_line)
+                .collect::<Vec<_>>();
+            body.push(serde_json::json!({
+                "type": "Container",
+                "separator": true,
+                "items": [
+                    { "type": "TextBlock", "text": project.name, "weight": "Bolder", "wrap": true },
+                    // Teams needs a blank line to break lines in the TextBlock.
+                    { "type": "TextBlock", "text": lines.join("\n\n"), "wrap": true },
+                ],
+            }));
+        }
+        Ok(wrap_card(serde_json::json!({
+            "type": "AdaptiveCard",
+            "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+            "version": "1.4",
+            "msteams": { "width": "Full" },
+            "body": body,
+        })))
+    }
+
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        crate::webhook::post_json(self.retry, self.notify_url, payload, "teams")?;
+        Ok(())
+    }
+}
+
+/// Render the card template, which outputs JSON of the Adaptive Card.
+fn convert_to_card(
+    template_path: &Option<String>,
+    update: &crate::redmine::UpdateInfo,
+) -> anyhow::Result<serde_json::Value> {
+    let template = crate::template::Template::load(
+        template_path,
+        "teams-card.tera",
+        include_str!("../resources/teams-card.tera"),
+    );
+    let card = template.render(update)?;
+    serde_json::from_str(&card)
+        .map_err(|e| anyhow::anyhow!("Teams card template did not output valid JSON with {e}"))
+}
+
+/// Message with the Adaptive Card attachment.
+fn wrap_card(card: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "contentUrl": null,
+            "content": card,
+        }],
+    })
+}
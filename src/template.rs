@@ -0,0 +1,594 @@
+// Copyright © ArkBig
+//! This file provides template rendering for notification messages.
+//!
+//! The engine is selected by the template file extension.
+//!
+//! - `*.tera`: [Tera](https://keats.github.io/tera/docs/) (conditionals, comparisons, filters and includes)
+//! - Otherwise: [TinyTemplate](https://docs.rs/tinytemplate/)
+//!
+//! In addition to the TinyTemplate syntax, the following formatters are available as `{value | formatter}`.
+//!
+//! - `date:<PATTERN>`: Format date/time with chrono pattern. (e.g. `{local_updated_time | date:%m/%d %H:%M}`)
+//! - `ago`: Relative time from now. (e.g. `3h ago`)
+//! - `truncate:<NUM>`: Truncate to NUM characters and append `...`.
+//! - `slack_escape`: Escape `&`, `<` and `>` for Slack mrkdwn.
+//! - `quote`: Prefix the continuation lines with `> `, so that multi-line text stays in the quote.
+//! - `upper`, `lower`: Change case.
+//! - `join:<SEP>`: Join array items with SEP. (Objects are joined by their `name`.)
+//!   SEP defaults to `, `, and spaces around it are trimmed as TinyTemplate trims the formatter.
+//! - `default:<TEXT>`: TEXT if the value is null or empty.
+//! - `plural:<SINGULAR>,<PLURAL>`: SINGULAR if the value is 1, otherwise PLURAL. (e.g. `{count} {count | plural:update,updates}`)
+//! - `unescaped`: Same as the default formatter.
+//! - `json`: JSON literal of the value. (e.g. for Adaptive Card templates)
+//!
+//! Tera templates can use its built-in filters, plus `ago`, `slack_escape` and `quote`.
+
+/// Loaded templates by the path (or the built-in name), so that each one is parsed once.
+static LOADED: std::sync::Mutex<std::collections::BTreeMap<String, std::sync::Arc<Template>>> =
+    std::sync::Mutex::new(std::collections::BTreeMap::new());
+
+/// Loaded template
+pub struct Template {
+    name: String,
+    text: String,
+    /// Parsed Tera templates including the referenced ones. (None for TinyTemplate)
+    tera: Option<tera::Tera>,
+}
+
+impl Template {
+    /// Load the template from the file, or use the built-in one if not specified.
+    ///
+    /// The built-in one is rendered with Tera if the name ends with `.tera`.
+    /// It is parsed only once, and if the file cannot be read or parsed, it exits with an error.
+    pub fn load(path: &Option<String>, name: &str, builtin: &str) -> std::sync::Arc<Self> {
+        let key = path.clone().unwrap_or_else(|| format!("builtin:{name}"));
+        let mut loaded = LOADED.lock().unwrap_or_else(|e| e.into_inner());
+        let template = loaded
+            .entry(key)
+            .or_insert_with(|| std::sync::Arc::new(Template::parse(path, name, builtin)));
+        template.clone()
+    }
+
+    fn parse(path: &Option<String>, name: &str, builtin: &str) -> Self {
+        let Some(path) = path else {
+            let template = if name.ends_with(".tera") {
+                build_tera(name, builtin, None)
+            } else {
+                build_tiny_template(name, builtin)
+            };
+            return template.unwrap_or_else(|e| {
+                let msg = format!("Could not parse built-in template [{name}] with {e:#}");
+                crate::log::error(&msg).category("template");
+                std::process::exit(1);
+            });
+        };
+        let text = std::fs::read_to_string(path).unwrap_or_else(|e| {
+            let msg = format!(
+                "Could not read template from file [{path}] with {e}",
+                path = path,
+                e = e
+            );
+            crate::log::error(&msg).category("template");
+            std::process::exit(1);
+        });
+        let file = std::path::Path::new(path);
+        let name = file
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| name.to_string());
+        let template = if file.extension().is_some_and(|ext| ext == "tera") {
+            build_tera(&name, &text, file.parent())
+        } else {
+            build_tiny_template(&name, &text)
+        };
+        template.unwrap_or_else(|e| {
+            let msg = format!("Could not parse template [{path}] with {e:#}");
+            crate::log::error(&msg).category("template");
+            std::process::exit(1);
+        })
+    }
+
+    /// Load the template files in advance to report errors at startup.
+    ///
+    /// Templates for each event kind in `dir` are also loaded.
+    pub fn preload(paths: &[&Option<String>], dir: &Option<String>) {
+        for path in paths.iter().filter(|p| p.is_some()) {
+            Template::load(path, "", "");
+        }
+        let Some(dir) = dir else {
+            return;
+        };
+        for event in crate::redmine::EventKind::ALL {
+            for ext in ["tera", "template"] {
+                let event_path =
+                    std::path::Path::new(dir).join(format!("{}.{}", event.as_str(), ext));
+                if event_path.is_file() {
+                    Template::load(&Some(event_path.to_string_lossy().to_string()), "", "");
+                }
+            }
+        }
+    }
+
+    /// Load the template for the event kind from the template set directory.
+    ///
+    /// `{event}.tera` or `{event}.template` in `dir` is used (e.g. `closed.template`),
+    /// otherwise it falls back to [`Template::load`].
+    pub fn load_for_event(
+        dir: &Option<String>,
+        event: crate::redmine::EventKind,
+        path: &Option<String>,
+        name: &str,
+        builtin: &str,
+    ) -> std::sync::Arc<Self> {
+        if let Some(dir) = dir {
+            for ext in ["tera", "template"] {
+                let event_path =
+                    std::path::Path::new(dir).join(format!("{}.{}", event.as_str(), ext));
+                if event_path.is_file() {
+                    return Template::load(
+                        &Some(event_path.to_string_lossy().to_string()),
+                        name,
+                        builtin,
+                    );
+                }
+            }
+        }
+        Template::load(path, name, builtin)
+    }
+
+    /// Render the template with the context such as [`crate::redmine::UpdateInfo`].
+    pub fn render<C: serde::Serialize>(&self, context: &C) -> anyhow::Result<String> {
+        match &self.tera {
+            None => render(&self.name, &self.text, context),
+            Some(tera) => {
+                let context = tera::Context::from_serialize(context)?;
+                Ok(tera.render(&self.name, &context)?)
+            }
+        }
+    }
+}
+
+/// Check the syntax and the formatters of the TinyTemplate.
+fn build_tiny_template(name: &str, text: &str) -> anyhow::Result<Template> {
+    let mut tt = tinytemplate::TinyTemplate::new();
+    tt.add_template(name, text)?;
+    check_formatters(text)?;
+    Ok(Template {
+        name: name.to_string(),
+        text: text.to_string(),
+        tera: None,
+    })
+}
+
+/// Parse the Tera template with the templates referenced by include, extends and import in `dir`.
+fn build_tera(name: &str, text: &str, dir: Option<&std::path::Path>) -> anyhow::Result<Template> {
+    let reference_re =
+        regex::Regex::new(r#"\{%-?\s*(?:include|extends|import)\s+["']([^"']+)["']"#).unwrap();
+    let mut tera = tera::Tera::default();
+    let mut templates = vec![(name.to_string(), text.to_string())];
+    let mut pending = vec![text.to_string()];
+    while let Some(text) = pending.pop() {
+        let Some(dir) = dir else {
+            break;
+        };
+        for reference in reference_re.captures_iter(&text) {
+            let reference = reference[1].to_string();
+            if templates.iter().any(|(n, _)| *n == reference) {
+                continue;
+            }
+            let path = dir.join(&reference);
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Could not read [{}] with {e}", path.display()))?;
+            pending.push(text.clone());
+            templates.push((reference, text));
+        }
+    }
+    tera.add_raw_templates(templates)?;
+    tera.register_filter("ago", tera_ago);
+    tera.register_filter("slack_escape", tera_slack_escape);
+    tera.register_filter("quote", tera_quote);
+    Ok(Template {
+        name: name.to_string(),
+        text: text.to_string(),
+        tera: Some(tera),
+    })
+}
+
+/// Render the TinyTemplate with the context.
+///
+/// Keys of `new_issue` and `old_items` referenced in the template are added as null
+/// so that `{{ if new_issue.xxx }}` can be used even if Redmine does not return it.
+fn render<C: serde::Serialize>(name: &str, template: &str, context: &C) -> anyhow::Result<String> {
+    let mut context = serde_json::to_value(context)?;
+    let new_referenced_re = regex::Regex::new(r"\Wnew_issue.(?<key>[a-zA-Z_-]+)").unwrap();
+    let old_referenced_re = regex::Regex::new(r"\Wold_items.(?<key>[a-zA-Z_-]+)").unwrap();
+    let new_keys = new_referenced_re
+        .captures_iter(template)
+        .map(|c| c["key"].to_string())
+        .collect::<Vec<_>>();
+    let old_keys = old_referenced_re
+        .captures_iter(template)
+        .map(|c| c["key"].to_string())
+        .collect::<Vec<_>>();
+    add_referenced_keys(&mut context, &new_keys, &old_keys);
+
+    // Render template
+    let mut tt = tinytemplate::TinyTemplate::new();
+    tt.set_default_formatter(&tinytemplate::format_unescaped);
+    add_formatters(&mut tt, template);
+    tt.add_template(name, template)?;
+    let msg = tt.render(name, &context)?;
+
+    Ok(msg)
+}
+
+/// Add referenced keys of new_issue and old_items (including nested ones such as in a digest).
+fn add_referenced_keys(value: &mut serde_json::Value, new_keys: &[String], old_keys: &[String]) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (field, keys) in [("new_issue", new_keys), ("old_items", old_keys)] {
+                if let Some(serde_json::Value::Object(map)) = map.get_mut(field) {
+                    for key in keys {
+                        if !map.contains_key(key) {
+                            map.insert(key.clone(), serde_json::Value::Null);
+                        }
+                    }
+                }
+            }
+            for (_, child) in map.iter_mut() {
+                add_referenced_keys(child, new_keys, old_keys);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                add_referenced_keys(item, new_keys, old_keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Formatters available in TinyTemplate.
+const FORMATTERS: [&str; 12] = [
+    "date",
+    "ago",
+    "truncate",
+    "slack_escape",
+    "quote",
+    "upper",
+    "lower",
+    "join",
+    "default",
+    "plural",
+    "unescaped",
+    "json",
+];
+
+/// Formatters referenced in the template as `(spec, name, arg)`.
+fn formatter_specs(template: &str) -> Vec<(&str, &str, String)> {
+    let tag_re = regex::Regex::new(r"\{([^{}]*)\}").unwrap();
+    let mut specs = Vec::new();
+    for tag in tag_re.captures_iter(template) {
+        let tag = tag.get(1).unwrap().as_str().trim();
+        let tag = tag.trim_start_matches('-').trim_end_matches('-');
+        let Some(index) = tag.find('|') else {
+            continue;
+        };
+        let spec = tag[index + 1..].trim();
+        let (name, arg) = match spec.find(':') {
+            Some(index) => (&spec[..index], spec[index + 1..].trim_start().to_string()),
+            None => (spec, String::new()),
+        };
+        specs.push((spec, name, arg));
+    }
+    specs
+}
+
+/// Check the formatters referenced in the template, so that a wrong one is reported at startup.
+fn check_formatters(template: &str) -> anyhow::Result<()> {
+    for (spec, name, arg) in formatter_specs(template) {
+        match name {
+            "truncate" => {
+                arg.parse::<usize>()
+                    .map_err(|e| anyhow::anyhow!("Invalid truncate length [{arg}] with {e}"))?;
+            }
+            "plural" if !arg.contains(',') => {
+                anyhow::bail!("Expected plural:<SINGULAR>,<PLURAL>, but [{spec}]")
+            }
+            name if !FORMATTERS.contains(&name) => anyhow::bail!("Unknown formatter [{spec}]"),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Register formatters referenced in the template.
+///
+/// TinyTemplate does not support formatter arguments, so each `name:arg` is registered as it is.
+/// Invalid arguments are errors at render time. (They are checked at startup by [`check_formatters`].)
+fn add_formatters<'a>(tt: &mut tinytemplate::TinyTemplate<'a>, template: &'a str) {
+    for (spec, name, arg) in formatter_specs(template) {
+        match name {
+            "date" => tt.add_formatter(spec, move |v, out| format_date(v, &arg, out)),
+            "ago" => tt.add_formatter(spec, format_ago),
+            "truncate" => tt.add_formatter(spec, move |v, out| {
+                let num =
+                    arg.parse::<usize>()
+                        .map_err(|e| tinytemplate::error::Error::GenericError {
+                            msg: format!("Invalid truncate length [{arg}] with {e}"),
+                        })?;
+                format_truncate(v, num, out)
+            }),
+            "slack_escape" => tt.add_formatter(spec, format_slack_escape),
+            "quote" => tt.add_formatter(spec, |v, out| {
+                out.push_str(&quote(&to_plain_string(v)?));
+                Ok(())
+            }),
+            "upper" => tt.add_formatter(spec, |v, out| {
+                out.push_str(&to_plain_string(v)?.to_uppercase());
+                Ok(())
+            }),
+            "lower" => tt.add_formatter(spec, |v, out| {
+                out.push_str(&to_plain_string(v)?.to_lowercase());
+                Ok(())
+            }),
+            "join" => tt.add_formatter(spec, move |v, out| format_join(v, &arg, out)),
+            "default" => tt.add_formatter(spec, move |v, out| {
+                let value = to_plain_string(v)?;
+                out.push_str(if value.is_empty() { &arg } else { &value });
+                Ok(())
+            }),
+            "plural" => tt.add_formatter(spec, move |v, out| format_plural(v, &arg, out)),
+            "unescaped" => tt.add_formatter(spec, tinytemplate::format_unescaped),
+            "json" => tt.add_formatter(spec, |v, out| {
+                out.push_str(&v.to_string());
+                Ok(())
+            }),
+            // Unknown formatters are reported by TinyTemplate at render time.
+            _ => {}
+        }
+    }
+}
+
+fn to_plain_string(value: &serde_json::Value) -> tinytemplate::error::Result<String> {
+    let mut s = String::new();
+    tinytemplate::format_unescaped(value, &mut s)?;
+    Ok(s)
+}
+
+fn format_date(
+    value: &serde_json::Value,
+    pattern: &str,
+    out: &mut String,
+) -> tinytemplate::error::Result<()> {
+    let value = to_plain_string(value)?;
+    let pattern = if pattern.is_empty() {
+        "%Y-%m-%d %H:%M"
+    } else {
+        pattern
+    };
+    match parse_date(&value) {
+        Some(date) => {
+            use std::fmt::Write as _;
+            // An invalid pattern is reported as an error instead of panic.
+            write!(out, "{}", date.format(pattern)).map_err(|_| {
+                tinytemplate::error::Error::GenericError {
+                    msg: format!("Invalid date pattern [{pattern}]"),
+                }
+            })?;
+        }
+        None => out.push_str(&value),
+    }
+    Ok(())
+}
+
+fn format_ago(value: &serde_json::Value, out: &mut String) -> tinytemplate::error::Result<()> {
+    let value = to_plain_string(value)?;
+    let Some(date) = parse_date(&value) else {
+        out.push_str(&value);
+        return Ok(());
+    };
+    out.push_str(&ago(&date));
+    Ok(())
+}
+
+fn ago(date: &chrono::DateTime<chrono::FixedOffset>) -> String {
+    let secs = (chrono::Utc::now() - date.with_timezone(&chrono::Utc)).num_seconds();
+    match secs {
+        s if s < 60 => "just now".to_string(),
+        s if s < 3600 => format!("{}m ago", s / 60),
+        s if s < 86400 => format!("{}h ago", s / 3600),
+        s => format!("{}d ago", s / 86400),
+    }
+}
+
+/// Parse RFC3339 date/time, or date only (e.g. due_date).
+fn parse_date(value: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(date);
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .map(|d| d.and_hms_opt(0, 0, 0).unwrap().and_utc().fixed_offset())
+}
+
+fn format_truncate(
+    value: &serde_json::Value,
+    num: usize,
+    out: &mut String,
+) -> tinytemplate::error::Result<()> {
+    let value = to_plain_string(value)?;
+    if num < value.chars().count() {
+        out.push_str(&value.chars().take(num).collect::<String>());
+        out.push_str("...");
+    } else {
+        out.push_str(&value);
+    }
+    Ok(())
+}
+
+fn format_plural(
+    value: &serde_json::Value,
+    forms: &str,
+    out: &mut String,
+) -> tinytemplate::error::Result<()> {
+    let Some((singular, plural)) = forms.split_once(',') else {
+        return Err(tinytemplate::error::Error::GenericError {
+            msg: format!("Expected plural:<SINGULAR>,<PLURAL>, but [{forms}]"),
+        });
+    };
+    let is_one = match value {
+        serde_json::Value::Array(items) => items.len() == 1,
+        _ => to_plain_string(value)?.trim() == "1",
+    };
+    out.push_str(if is_one {
+        singular.trim()
+    } else {
+        plural.trim()
+    });
+    Ok(())
+}
+
+fn format_slack_escape(
+    value: &serde_json::Value,
+    out: &mut String,
+) -> tinytemplate::error::Result<()> {
+    out.push_str(&slack_escape(&to_plain_string(value)?));
+    Ok(())
+}
+
+/// Escape control characters of Slack mrkdwn.
+pub fn slack_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Prefix the continuation lines with `> `.
+fn quote(value: &str) -> String {
+    value.replace('\n', "\n> ")
+}
+
+fn format_join(
+    value: &serde_json::Value,
+    sep: &str,
+    out: &mut String,
+) -> tinytemplate::error::Result<()> {
+    let sep = if sep.is_empty() { ", " } else { sep };
+    match value {
+        serde_json::Value::Array(items) => {
+            let items = items
+                .iter()
+                .map(|item| match item {
+                    serde_json::Value::Object(map) if map.contains_key("name") => {
+                        to_plain_string(&map["name"])
+                    }
+                    _ => to_plain_string(item),
+                })
+                .collect::<tinytemplate::error::Result<Vec<_>>>()?;
+            out.push_str(&items.join(sep));
+            Ok(())
+        }
+        _ => tinytemplate::format_unescaped(value, out),
+    }
+}
+
+fn tera_ago(
+    value: &tera::Value,
+    _: &std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let value = tera::from_value::<String>(value.clone())?;
+    match parse_date(&value) {
+        Some(date) => Ok(tera::Value::String(ago(&date))),
+        None => Ok(tera::Value::String(value)),
+    }
+}
+
+fn tera_slack_escape(
+    value: &tera::Value,
+    _: &std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let value = tera::from_value::<String>(value.clone())?;
+    Ok(tera::Value::String(slack_escape(&value)))
+}
+
+fn tera_quote(
+    value: &tera::Value,
+    _: &std::collections::HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let value = tera::from_value::<String>(value.clone())?;
+    Ok(tera::Value::String(quote(&value)))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn date_formats_and_rejects_invalid_pattern() {
+        let context = serde_json::json!({ "date": "2024-01-02T03:04:05+09:00" });
+        let text = super::render("t", "{date | date:%m/%d %H:%M}", &context).unwrap();
+        assert_eq!(text, "01/02 03:04");
+
+        let err = super::render("t", "{date | date:%Q}", &context).unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid date pattern [%Q]"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn tera_loads_only_referenced_templates() {
+        let dir = std::env::temp_dir().join(format!("tera-ref-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("part.tera"), "[{{ id }}]").unwrap();
+        std::fs::write(dir.join("broken.tera"), "{% if %}").unwrap();
+
+        let text = r#"#{% include "part.tera" %}"#;
+        let template = super::build_tera("main.tera", text, Some(&dir)).unwrap();
+        let rendered = template.render(&serde_json::json!({ "id": 1 })).unwrap();
+        assert_eq!(rendered, "#[1]");
+
+        let text = r#"{% include "broken.tera" %}"#;
+        assert!(super::build_tera("main.tera", text, Some(&dir)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn join_uses_separator() {
+        let context = serde_json::json!({ "names": ["a", { "name": "b" }] });
+        let text = super::render("t", "{names | join}", &context).unwrap();
+        assert_eq!(text, "a, b");
+        let text = super::render("t", "{names | join:/}", &context).unwrap();
+        assert_eq!(text, "a/b");
+    }
+
+    #[test]
+    fn plural_by_count() {
+        let template = "{count} {count | plural:update,updates}";
+        let text = super::render("t", template, &serde_json::json!({ "count": 1 })).unwrap();
+        assert_eq!(text, "1 update");
+        let text = super::render("t", template, &serde_json::json!({ "count": 3 })).unwrap();
+        assert_eq!(text, "3 updates");
+    }
+
+    #[test]
+    fn builtin_templates_use_valid_formatters() {
+        for entry in std::fs::read_dir("resources").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "template") {
+                let text = std::fs::read_to_string(&path).unwrap();
+                assert!(super::check_formatters(&text).is_ok(), "{:?}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_formatter_is_error_not_exit() {
+        let context = serde_json::json!({ "subject": "Crash on save" });
+        assert!(super::render("t", "{subject | truncate:x}", &context).is_err());
+        assert!(super::build_tiny_template("t", "{subject | truncate:x}").is_err());
+        assert!(super::build_tiny_template("t", "{subject | plural:one}").is_err());
+        assert!(super::build_tiny_template("t", "{subject | shout}").is_err());
+        assert!(super::build_tiny_template("t", "{subject | truncate:5}").is_ok());
+    }
+}
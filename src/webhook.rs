@@ -0,0 +1,180 @@
+// Copyright © ArkBig
+//! This file provides the generic webhook and posting to webhooks with retry.
+
+/// Maximum wait for a retry, even if the destination asks for longer.
+const MAX_RETRY_WAIT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Generic HTTP webhook
+///
+/// The request body is JSON rendered from the template, or [`crate::redmine::UpdateInfo`] itself.
+pub struct Webhook<'a> {
+    args: &'a crate::cli_args::WebhookArgs,
+    retry: &'a crate::cli_args::RetryArgs,
+    url: &'a str,
+}
+
+impl<'a> Webhook<'a> {
+    pub fn new(
+        args: &'a crate::cli_args::WebhookArgs,
+        retry: &'a crate::cli_args::RetryArgs,
+        url: &'a str,
+    ) -> Self {
+        Webhook { args, retry, url }
+    }
+}
+
+impl crate::notifier::Notifier for Webhook<'_> {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn render(&self, update: &crate::redmine::UpdateInfo) -> anyhow::Result<serde_json::Value> {
+        if self.args.webhook_template_path.is_none() {
+            return Ok(serde_json::to_value(update)?);
+        }
+        let template =
+            crate::template::Template::load(&self.args.webhook_template_path, "webhook-body", "");
+        let body = template.render(update)?;
+        serde_json::from_str(&body).map_err(|e| {
+            anyhow::anyhow!("Webhook body template did not output valid JSON with {e}")
+        })
+    }
+
+    fn deliver(&self, payload: &serde_json::Value) -> anyhow::Result<()> {
+        let body = payload.to_string();
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        headers.extend(
+            self.args
+                .webhook_header
+                .iter()
+                .map(|h| (h.name.clone(), h.value.clone())),
+        );
+        if let Some(secret) = &self.args.webhook_hmac_secret {
+            headers.push((
+                self.args.webhook_hmac_header.clone(),
+                format!("sha256={}", sign(secret, &body)),
+            ));
+        }
+        send(
+            self.retry,
+            &self.args.webhook_method,
+            self.url,
+            &headers,
+            &body,
+            "webhook",
+        )?;
+        Ok(())
+    }
+}
+
+/// HTTP header of the request
+///
+/// Format: `<NAME>:<VALUE>` (e.g. `Authorization: Bearer xxx`)
+#[derive(Clone, Debug)]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Expected <NAME>:<VALUE>, but [{s}]"))?;
+        Ok(Header {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+        })
+    }
+}
+
+/// HMAC-SHA256 of the body in hex.
+fn sign(secret: &str, body: &str) -> String {
+    use hmac::Mac as _;
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC can take key of any size");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Post the JSON body to the webhook URL.
+///
+/// See [`send`] for the retry.
+pub fn post_json(
+    args: &crate::cli_args::RetryArgs,
+    url: &str,
+    body: &serde_json::Value,
+    category: &str,
+) -> anyhow::Result<ureq::Response> {
+    let headers = [("Content-Type".to_string(), "application/json".to_string())];
+    send(args, "POST", url, &headers, &body.to_string(), category)
+}
+
+/// Send the request to the URL.
+///
+/// Network errors, 429 Too Many Requests and 5xx server errors are retried
+/// up to `--retry` times with exponential backoff.
+/// If the destination returns `Retry-After`, it is used as the wait instead.
+pub fn send(
+    args: &crate::cli_args::RetryArgs,
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: &str,
+    category: &str,
+) -> anyhow::Result<ureq::Response> {
+    let mut attempt = 0;
+    loop {
+        let mut request = ureq::request(method, url);
+        for (name, value) in headers {
+            request = request.set(name, value);
+        }
+        let result = request.send_string(body);
+        let (wait, err) = match result {
+            Ok(res) => return Ok(res),
+            Err(ureq::Error::Status(status, res)) if status == 429 || 500 <= status => {
+                let wait = retry_after(&res).unwrap_or_else(|| backoff(args, attempt));
+                (wait, ureq::Error::Status(status, res))
+            }
+            Err(err @ ureq::Error::Transport(_)) => (backoff(args, attempt), err),
+            Err(err) => return Err(err.into()),
+        };
+        if args.retry <= attempt {
+            return Err(err.into());
+        }
+        attempt += 1;
+        crate::metrics::inc_retry(category);
+        let fields = retry_fields(category, attempt, wait);
+        crate::log::warning(&format!("Retry posting with {}", err))
+            .category(category)
+            .json(&fields);
+        std::thread::sleep(wait);
+    }
+}
+
+/// Structured log fields of the retry.
+pub fn retry_fields(sink: &str, attempt: u32, wait: std::time::Duration) -> serde_json::Value {
+    serde_json::json!({
+        "sink": sink,
+        "attempt": attempt,
+        "wait": humantime::format_duration(wait).to_string(),
+    })
+}
+
+/// Wait for the attempt with exponential backoff.
+pub fn backoff(args: &crate::cli_args::RetryArgs, attempt: u32) -> std::time::Duration {
+    args.retry_wait
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_RETRY_WAIT)
+}
+
+/// Wait specified by the `Retry-After` header in seconds.
+pub fn retry_after(res: &ureq::Response) -> Option<std::time::Duration> {
+    let secs = res.header("Retry-After")?.trim().parse::<f64>().ok()?;
+    if !secs.is_finite() || secs < 0.0 {
+        return None;
+    }
+    Some(std::time::Duration::from_secs_f64(secs).min(MAX_RETRY_WAIT))
+}